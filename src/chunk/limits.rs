@@ -0,0 +1,27 @@
+/// Upper bounds applied by the dechunker to everything a peer can make us
+/// allocate. Exceeding any of them fails the read with a `ProtocolError`.
+#[derive(Debug, Clone)]
+pub struct ChunkLimits {
+    /// Largest `message_length` accepted in a chunk message header.
+    pub max_message_size: u32,
+
+    /// Largest chunk size a peer may announce through SetChunkSize.
+    pub max_chunk_size: u32,
+
+    /// How many chunk streams may have a partially received message at once.
+    pub max_partial_messages: usize,
+
+    /// Total payload bytes buffered across all partial messages.
+    pub max_buffered_bytes: usize,
+}
+
+impl Default for ChunkLimits {
+    fn default() -> Self {
+        ChunkLimits {
+            max_message_size: 8 * 1024 * 1024,
+            max_chunk_size: 16 * 1024 * 1024,
+            max_partial_messages: 64,
+            max_buffered_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
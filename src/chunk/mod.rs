@@ -1,3 +1,5 @@
+pub mod limits;
 pub mod packets;
+pub mod scheduler;
 pub mod writer;
-pub mod reader;
\ No newline at end of file
+pub mod reader;
@@ -148,19 +148,22 @@ pub struct ChunkBasicHeader {
     pub chunk_stream_id: u32,     // Variable length, up to 24 bits, renamed from 'cs_id'
 }
 
+/// Timestamp field value signaling that the real value follows as an extended timestamp.
+pub const EXTENDED_TIMESTAMP_MARKER: u32 = 0xFFFFFF;
+
 /// Represents the different types of message headers in RTMP chunks.
 #[derive(Debug)]
 pub enum ChunkMessageHeader {
     Type0 {
         absolute_timestamp: u32, // can be extended
         message_length: u32,
-        message_type_id: MessageTypeId,
+        message_type_id: u8,          // raw value, see MessageTypeId
         message_stream_id: u32,       // 4 bytes, little-endian
     },
     Type1 {
         timestamp_delta: u32, // can be extended
         message_length: u32,
-        message_type_id: MessageTypeId,
+        message_type_id: u8,
     },
     Type2 {
         timestamp_delta: u32, // can be extended
@@ -171,9 +174,9 @@ pub enum ChunkMessageHeader {
 impl ChunkMessageHeader {
     pub fn is_extended_timestamp(&self) -> bool {
         match self {
-            ChunkMessageHeader::Type0 { absolute_timestamp, .. } => *absolute_timestamp == 0xFFFFFF,
-            ChunkMessageHeader::Type1 { .. } => false,
-            ChunkMessageHeader::Type2 { .. } => false,
+            ChunkMessageHeader::Type0 { absolute_timestamp, .. } => *absolute_timestamp == EXTENDED_TIMESTAMP_MARKER,
+            ChunkMessageHeader::Type1 { timestamp_delta, .. } => *timestamp_delta == EXTENDED_TIMESTAMP_MARKER,
+            ChunkMessageHeader::Type2 { timestamp_delta } => *timestamp_delta == EXTENDED_TIMESTAMP_MARKER,
             ChunkMessageHeader::Type3 => false,
         }
     }
@@ -1,9 +1,60 @@
-use crate::chunk::packets::{ChunkBasicHeader, ChunkMessageHeader, ExtendedTimestamp, RTMPChunk};
+use crate::chunk::packets::{ChunkBasicHeader, ChunkMessageHeader, ExtendedTimestamp};
 use crate::context::NetConnectionContext;
+use crate::errors::ProtocolError;
 use crate::net_connection::packets::RTMPMessage;
 use crate::transport::Transport;
 
-use super::packets::MessageTypeId;
+
+/// State kept per incoming chunk stream, later chunks only carry the fields
+/// that changed and the payload of a message may span several chunks.
+#[derive(Debug)]
+pub struct InboundChunkStream {
+    /// Absolute timestamp of the current message.
+    pub timestamp: u32,
+    /// Delta applied when a type 3 chunk starts a new message.
+    pub timestamp_delta: u32,
+    /// Whether the last full header used an extended timestamp, in which case
+    /// type 3 chunks repeat it.
+    pub has_extended_timestamp: bool,
+    pub message_length: u32,
+    pub message_type_id: u8,
+    pub message_stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl InboundChunkStream {
+    /// Applies the timestamp fields of a chunk header. Only chunks starting a
+    /// message move the timestamp forward, timestamps wrap around at 2^32.
+    fn apply_timestamp(&mut self, message_header: &ChunkMessageHeader, extended_timestamp: Option<ExtendedTimestamp>) {
+        let starts_message = self.payload.is_empty();
+
+        match message_header {
+            ChunkMessageHeader::Type0 { absolute_timestamp, .. } => {
+                let timestamp = extended_timestamp.map_or(*absolute_timestamp, |ets| ets.0);
+
+                self.timestamp = timestamp;
+                // a type 3 chunk following a type 0 one reuses its timestamp as delta
+                self.timestamp_delta = timestamp;
+                self.has_extended_timestamp = message_header.is_extended_timestamp();
+            }
+            ChunkMessageHeader::Type1 { timestamp_delta, .. } | ChunkMessageHeader::Type2 { timestamp_delta } => {
+                let timestamp_delta = extended_timestamp.map_or(*timestamp_delta, |ets| ets.0);
+
+                self.timestamp_delta = timestamp_delta;
+                self.has_extended_timestamp = message_header.is_extended_timestamp();
+
+                if starts_message {
+                    self.timestamp = self.timestamp.wrapping_add(timestamp_delta);
+                }
+            }
+            ChunkMessageHeader::Type3 => {
+                if starts_message {
+                    self.timestamp = self.timestamp.wrapping_add(self.timestamp_delta);
+                }
+            }
+        }
+    }
+}
 
 pub struct RTMPDechunker {
 
@@ -25,7 +76,7 @@ impl RTMPDechunker {
             1 => {
                 let second_byte = context.transport.read_u8()?;
                 let third_byte = context.transport.read_u8()?;
-                64 + second_byte as u32 + third_byte as u32
+                64 + second_byte as u32 + ((third_byte as u32) << 8)
             }
             _ => chunk_stream_id as u32,
         };
@@ -50,13 +101,8 @@ impl RTMPDechunker {
                 let message_length = message_length << 8 | context.transport.read_u8()? as u32;
                 let message_length = message_length << 8 | context.transport.read_u8()? as u32;
 
-                let message_type_id = MessageTypeId::try_from(context.transport.read_u8()?)
-                    .map_err(|_| {
-                        std::io::Error::new(
-                            std::io::ErrorKind::InvalidData,
-                            "Invalid message type ID",
-                        )
-                    })?;
+                // unknown message types are passed through as raw messages
+                let message_type_id = context.transport.read_u8()?;
 
                 let message_stream_id = context.transport.read_u32_le()?;
 
@@ -76,13 +122,8 @@ impl RTMPDechunker {
                 let message_length = message_length << 8 | context.transport.read_u8()? as u32;
                 let message_length = message_length << 8 | context.transport.read_u8()? as u32;
 
-                let message_type_id = MessageTypeId::try_from(context.transport.read_u8()?)
-                    .map_err(|_| {
-                        std::io::Error::new(
-                            std::io::ErrorKind::InvalidData,
-                            "Invalid message type ID",
-                        )
-                    })?;
+                // unknown message types are passed through as raw messages
+                let message_type_id = context.transport.read_u8()?;
 
                 Ok(ChunkMessageHeader::Type1 {
                     timestamp_delta: timestamp,
@@ -90,6 +131,15 @@ impl RTMPDechunker {
                     message_type_id,
                 })
             }
+            2 => {
+                let timestamp = context.transport.read_u8()? as u32;
+                let timestamp = timestamp << 8 | context.transport.read_u8()? as u32;
+                let timestamp = timestamp << 8 | context.transport.read_u8()? as u32;
+
+                Ok(ChunkMessageHeader::Type2 {
+                    timestamp_delta: timestamp,
+                })
+            }
             3 => Ok(ChunkMessageHeader::Type3),
             _ => Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -105,69 +155,273 @@ impl RTMPDechunker {
         Ok(ExtendedTimestamp(timestamp))
     }
 
-	pub fn read_chunks<T: Transport>(
-		context: &mut NetConnectionContext<T>,
-	) -> std::io::Result<RTMPMessage> {
-		let mut rtmp_chunks: Vec<RTMPChunk> = Vec::new();
-		let mut payload_size: Option<usize> = None;
+    fn read_payload<T: Transport>(
+        context: &mut NetConnectionContext<T>,
+        chunk_stream_id: u32,
+        message_header: &ChunkMessageHeader,
+        extended_timestamp: Option<ExtendedTimestamp>,
+    ) -> std::io::Result<Option<RTMPMessage>> {
+        let limits = &context.chunk_limits;
+
+        let partial_messages = context
+            .inbound_chunk_streams
+            .iter()
+            .filter(|(id, chunk_stream)| **id != chunk_stream_id && !chunk_stream.payload.is_empty())
+            .count();
 
-		loop {
-			if let Some(size) = payload_size {
-				if size == 0 {
-					break;
-				}
-			}
+        let chunk_stream = match message_header {
+            ChunkMessageHeader::Type0 { message_length, message_type_id, message_stream_id, .. } => {
+                let previous = context.inbound_chunk_streams.insert(chunk_stream_id, InboundChunkStream {
+                    timestamp: 0,
+                    timestamp_delta: 0,
+                    has_extended_timestamp: false,
+                    message_length: *message_length,
+                    message_type_id: *message_type_id,
+                    message_stream_id: *message_stream_id,
+                    payload: Vec::new(),
+                });
 
-			let basic_header = RTMPDechunker::read_basic_header(context)?;
-			let message_header = RTMPDechunker::read_message_header(context, basic_header.chunk_header_format)?;
+                // a new message header drops whatever was left of the previous one
+                if let Some(previous) = previous {
+                    context.buffered_bytes -= previous.payload.len();
+                }
 
-            if message_header.is_extended_timestamp() {
-                let ets = RTMPDechunker::read_extended_timestamp(context)?;
-                println!("Extended timestamp: {:?}", ets);
+                context.inbound_chunk_streams.get_mut(&chunk_stream_id).unwrap()
             }
+            _ => context
+                .inbound_chunk_streams
+                .get_mut(&chunk_stream_id)
+                .ok_or(ProtocolError::MissingChunkHeader(chunk_stream_id))?,
+        };
 
-			payload_size = match message_header {
-				ChunkMessageHeader::Type0 { message_length, .. } => Some(message_length as usize),
-				ChunkMessageHeader::Type1 { message_length, .. } => Some(message_length as usize),
-				_ => payload_size,
-			};
+        if let ChunkMessageHeader::Type1 { message_length, message_type_id, .. } = message_header {
+            // like a type 0 header, it starts a new message over the partial one
+            context.buffered_bytes -= chunk_stream.payload.len();
+            chunk_stream.payload.clear();
 
-			let read_size = std::cmp::min(payload_size.unwrap_or(context.chunk_size as usize), context.chunk_size as usize);
-			let payload = context.transport.read_data(read_size)?;
+            chunk_stream.message_length = *message_length;
+            chunk_stream.message_type_id = *message_type_id;
+        }
 
-			if let Some(size) = payload_size.as_mut() {
-				*size -= read_size;
-			}
+        chunk_stream.apply_timestamp(message_header, extended_timestamp);
 
-			rtmp_chunks.push(RTMPChunk {
-				basic_header,
-				message_header,
-				extended_timestamp: None,
-				data: payload,
-			});
-		}
+        if chunk_stream.payload.is_empty() {
+            if chunk_stream.message_length > limits.max_message_size {
+                return Err(ProtocolError::MessageTooLarge {
+                    size: chunk_stream.message_length,
+                    limit: limits.max_message_size,
+                }.into());
+            }
 
-        let payload = rtmp_chunks.iter().flat_map(|chunk| chunk.data.iter()).cloned().collect();
+            if partial_messages >= limits.max_partial_messages {
+                return Err(ProtocolError::TooManyPartialMessages {
+                    limit: limits.max_partial_messages,
+                }.into());
+            }
+        }
 
-        Ok(RTMPMessage {
-            timestamp: 0,
-            message_type_id: match rtmp_chunks[0].message_header {
-                ChunkMessageHeader::Type0 { message_type_id, .. } => message_type_id,
-                ChunkMessageHeader::Type1 { message_type_id, .. } => message_type_id,
-                _ => todo!("message type id chunk 0 is not type0"),
-            },
-            // todo: implement chunk stream id
-            chunk_stream_id: super::packets::ChunkImportance::CommandAMF0AMF3,
-            message_stream_id: match rtmp_chunks[0].message_header {
-                ChunkMessageHeader::Type0 { message_stream_id, .. } => message_stream_id,
-                ChunkMessageHeader::Type1 { .. } => {
-                    println!("TODO: Type1 message stream id");
-                    0
-                },
-                _ => todo!("message type id stream id is not type0"),
-            },
+        let remaining = chunk_stream.message_length as usize - chunk_stream.payload.len();
+        let read_size = std::cmp::min(remaining, context.in_chunk_size as usize);
+
+        if context.buffered_bytes + read_size > limits.max_buffered_bytes {
+            return Err(ProtocolError::BufferLimitExceeded {
+                size: context.buffered_bytes + read_size,
+                limit: limits.max_buffered_bytes,
+            }.into());
+        }
+
+        let data = context.transport.read_data(read_size)?;
+        chunk_stream.payload.extend_from_slice(&data);
+        context.buffered_bytes += read_size;
+
+        if chunk_stream.payload.len() < chunk_stream.message_length as usize {
+            return Ok(None);
+        }
+
+        let payload = std::mem::take(&mut chunk_stream.payload);
+        context.buffered_bytes -= payload.len();
+
+        Ok(Some(RTMPMessage {
+            timestamp: chunk_stream.timestamp,
+            message_type_id: chunk_stream.message_type_id,
+            chunk_stream_id,
+            message_stream_id: chunk_stream.message_stream_id,
             payload,
-        })
+        }))
+    }
+
+    /// Reads chunks until one message is complete. Chunks of other chunk
+    /// streams read in between are buffered until their own message completes.
+    pub fn read_chunks<T: Transport>(
+        context: &mut NetConnectionContext<T>,
+    ) -> std::io::Result<RTMPMessage> {
+        loop {
+            let basic_header = RTMPDechunker::read_basic_header(context)?;
+            let message_header = RTMPDechunker::read_message_header(context, basic_header.chunk_header_format)?;
+
+            let has_extended_timestamp = match message_header {
+                ChunkMessageHeader::Type3 => context
+                    .inbound_chunk_streams
+                    .get(&basic_header.chunk_stream_id)
+                    .is_some_and(|chunk_stream| chunk_stream.has_extended_timestamp),
+                _ => message_header.is_extended_timestamp(),
+            };
+
+            let extended_timestamp = if has_extended_timestamp {
+                Some(RTMPDechunker::read_extended_timestamp(context)?)
+            } else {
+                None
+            };
+
+            let message = RTMPDechunker::read_payload(
+                context,
+                basic_header.chunk_stream_id,
+                &message_header,
+                extended_timestamp,
+            )?;
+
+            if let Some(message) = message {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::limits::ChunkLimits;
+    use crate::chunk::packets::{MessageTypeId, EXTENDED_TIMESTAMP_MARKER};
+    use crate::context::allocate_net_connection_context;
+    use crate::transport::memory_transport::MemoryTransport;
+
+    fn chunk_stream() -> InboundChunkStream {
+        InboundChunkStream {
+            timestamp: 0,
+            timestamp_delta: 0,
+            has_extended_timestamp: false,
+            message_length: 0,
+            message_type_id: MessageTypeId::AudioData as u8,
+            message_stream_id: 1,
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_type3_repeats_delta() {
+        let mut chunk_stream = chunk_stream();
+
+        chunk_stream.apply_timestamp(&ChunkMessageHeader::Type0 {
+            absolute_timestamp: 1000,
+            message_length: 10,
+            message_type_id: MessageTypeId::AudioData as u8,
+            message_stream_id: 1,
+        }, None);
+        assert_eq!(chunk_stream.timestamp, 1000);
+
+        chunk_stream.apply_timestamp(&ChunkMessageHeader::Type2 { timestamp_delta: 23 }, None);
+        assert_eq!(chunk_stream.timestamp, 1023);
+
+        chunk_stream.apply_timestamp(&ChunkMessageHeader::Type3, None);
+        assert_eq!(chunk_stream.timestamp, 1046);
+
+        // continuation chunks don't move the timestamp
+        chunk_stream.payload.push(0);
+        chunk_stream.apply_timestamp(&ChunkMessageHeader::Type3, None);
+        assert_eq!(chunk_stream.timestamp, 1046);
+    }
+
+    #[test]
+    fn test_extended_timestamp_wraps() {
+        let mut chunk_stream = chunk_stream();
+
+        chunk_stream.apply_timestamp(&ChunkMessageHeader::Type0 {
+            absolute_timestamp: EXTENDED_TIMESTAMP_MARKER,
+            message_length: 10,
+            message_type_id: MessageTypeId::AudioData as u8,
+            message_stream_id: 1,
+        }, Some(ExtendedTimestamp(u32::MAX - 10)));
+        assert_eq!(chunk_stream.timestamp, u32::MAX - 10);
+        assert!(chunk_stream.has_extended_timestamp);
+
+        chunk_stream.apply_timestamp(&ChunkMessageHeader::Type1 {
+            timestamp_delta: 20,
+            message_length: 10,
+            message_type_id: MessageTypeId::AudioData as u8,
+        }, None);
+        assert_eq!(chunk_stream.timestamp, 9);
+        assert!(!chunk_stream.has_extended_timestamp);
+    }
+
+    fn type0_chunk(chunk_stream_id: u8, message_length: u32, payload: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![chunk_stream_id, 0, 0, 0];
+        chunk.extend_from_slice(&message_length.to_be_bytes()[1..]);
+        chunk.push(MessageTypeId::AudioData as u8);
+        chunk.extend_from_slice(&1u32.to_le_bytes());
+        chunk.extend_from_slice(payload);
+        chunk
+    }
+
+    fn type1_chunk(chunk_stream_id: u8, message_length: u32, payload: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![0x40 | chunk_stream_id, 0, 0, 0];
+        chunk.extend_from_slice(&message_length.to_be_bytes()[1..]);
+        chunk.push(MessageTypeId::AudioData as u8);
+        chunk.extend_from_slice(payload);
+        chunk
+    }
+
+    fn read_chunks(data: &[u8], chunk_limits: ChunkLimits) -> std::io::Result<RTMPMessage> {
+        let mut context = allocate_net_connection_context(MemoryTransport::with_data(data));
+        context.chunk_limits = chunk_limits;
+        context.in_chunk_size = 4;
+
+        RTMPDechunker::read_chunks(&mut context)
+    }
+
+    fn protocol_error(error: std::io::Error) -> ProtocolError {
+        error.into_inner().unwrap().downcast::<ProtocolError>().map(|error| *error).unwrap()
+    }
+
+    #[test]
+    fn test_message_too_large() {
+        let chunk_limits = ChunkLimits { max_message_size: 8, ..ChunkLimits::default() };
+
+        let error = read_chunks(&type0_chunk(4, 9, &[0; 4]), chunk_limits.clone()).unwrap_err();
+        assert_eq!(protocol_error(error), ProtocolError::MessageTooLarge { size: 9, limit: 8 });
+
+        // a type 1 header in the middle of a message can't grow it past the limit either
+        let mut data = type0_chunk(4, 8, &[0; 4]);
+        data.extend(type1_chunk(4, 1000, &[0; 4]));
+        let error = read_chunks(&data, chunk_limits).unwrap_err();
+        assert_eq!(protocol_error(error), ProtocolError::MessageTooLarge { size: 1000, limit: 8 });
+    }
+
+    #[test]
+    fn test_type1_restarts_message() {
+        let mut data = type0_chunk(4, 8, &[1; 4]);
+        data.extend(type1_chunk(4, 2, &[2; 2]));
+
+        let message = read_chunks(&data, ChunkLimits::default()).unwrap();
+        assert_eq!(message.payload, vec![2; 2]);
+    }
+
+    #[test]
+    fn test_too_many_partial_messages() {
+        let chunk_limits = ChunkLimits { max_partial_messages: 1, ..ChunkLimits::default() };
+
+        let mut data = type0_chunk(4, 8, &[0; 4]);
+        data.extend(type0_chunk(5, 8, &[0; 4]));
+        let error = read_chunks(&data, chunk_limits).unwrap_err();
+        assert_eq!(protocol_error(error), ProtocolError::TooManyPartialMessages { limit: 1 });
+    }
+
+    #[test]
+    fn test_buffer_limit() {
+        let chunk_limits = ChunkLimits { max_buffered_bytes: 6, ..ChunkLimits::default() };
 
+        let mut data = type0_chunk(4, 8, &[0; 4]);
+        data.extend(type0_chunk(5, 8, &[0; 4]));
+        let error = read_chunks(&data, chunk_limits).unwrap_err();
+        assert_eq!(protocol_error(error), ProtocolError::BufferLimitExceeded { size: 8, limit: 6 });
     }
 }
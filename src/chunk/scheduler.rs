@@ -0,0 +1,305 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::chunk::packets::{ChunkImportance, MessageTypeId};
+use crate::chunk::writer::RTMPChunker;
+use crate::context::MAX_CHUNK_SIZE;
+use crate::net_connection::packets::RTMPMessage;
+use crate::transport::Transport;
+
+/// Highest chunk stream id that fits in the 3 byte basic header form.
+pub const MAX_CHUNK_STREAM_ID: u32 = 65599;
+
+/// First chunk stream id handed out to message streams other than 0, the
+/// ones below are the fixed `ChunkImportance` values.
+const FIRST_DYNAMIC_CHUNK_STREAM_ID: u32 = 7;
+
+/// Order in which queued messages get to send their chunks, lower goes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MessagePriority {
+    Control = 0,
+    Command = 1,
+    Audio = 2,
+    Video = 3,
+}
+
+impl From<MessageTypeId> for MessagePriority {
+    fn from(message_type_id: MessageTypeId) -> Self {
+        match message_type_id {
+            MessageTypeId::SetChunkSize
+            | MessageTypeId::AbortMessage
+            | MessageTypeId::Acknowledgement
+            | MessageTypeId::UserControlMessage
+            | MessageTypeId::WindowAcknowledgementSize
+            | MessageTypeId::SetPeerBandwidth => MessagePriority::Control,
+            MessageTypeId::AudioData => MessagePriority::Audio,
+            MessageTypeId::VideoData | MessageTypeId::AggregateMessage => MessagePriority::Video,
+            _ => MessagePriority::Command,
+        }
+    }
+}
+
+impl MessagePriority {
+    /// Priority of a raw message type id, unknown types are treated as commands.
+    pub fn from_type_id(message_type_id: u8) -> Self {
+        MessageTypeId::try_from(message_type_id)
+            .map(MessagePriority::from)
+            .unwrap_or(MessagePriority::Command)
+    }
+}
+
+/// Hands out one chunk stream id per message stream and priority, so that
+/// messages which may be interleaved never share a chunk stream.
+#[derive(Debug)]
+pub struct ChunkStreamAllocator {
+    assigned: HashMap<(u32, MessagePriority), u32>,
+    next_chunk_stream_id: u32,
+}
+
+impl Default for ChunkStreamAllocator {
+    fn default() -> Self {
+        ChunkStreamAllocator {
+            assigned: HashMap::new(),
+            next_chunk_stream_id: FIRST_DYNAMIC_CHUNK_STREAM_ID,
+        }
+    }
+}
+
+impl ChunkStreamAllocator {
+    pub fn chunk_stream_id(
+        &mut self,
+        message_stream_id: u32,
+        priority: MessagePriority,
+    ) -> std::io::Result<u32> {
+        // protocol control messages always go over chunk stream 2
+        if priority == MessagePriority::Control {
+            return Ok(ChunkImportance::ProtocolUserControl as u32);
+        }
+
+        if message_stream_id == 0 {
+            return Ok(match priority {
+                MessagePriority::Audio => ChunkImportance::Audio as u32,
+                MessagePriority::Video => ChunkImportance::Video as u32,
+                _ => ChunkImportance::CommandAMF0AMF3 as u32,
+            });
+        }
+
+        if let Some(chunk_stream_id) = self.assigned.get(&(message_stream_id, priority)) {
+            return Ok(*chunk_stream_id);
+        }
+
+        if self.next_chunk_stream_id > MAX_CHUNK_STREAM_ID {
+            return Err(std::io::Error::other("Ran out of chunk stream ids"));
+        }
+
+        let chunk_stream_id = self.next_chunk_stream_id;
+        self.next_chunk_stream_id += 1;
+        self.assigned.insert((message_stream_id, priority), chunk_stream_id);
+
+        Ok(chunk_stream_id)
+    }
+
+    /// Forgets the chunk streams of a deleted message stream.
+    pub fn release(&mut self, message_stream_id: u32) {
+        self.assigned.retain(|(stream_id, _), _| *stream_id != message_stream_id);
+    }
+}
+
+#[derive(Debug)]
+struct QueuedMessage {
+    message: RTMPMessage,
+    priority: MessagePriority,
+    offset: usize,
+}
+
+/// Queues outgoing messages and interleaves their chunks. Every step sends
+/// one chunk of the highest priority message, rotating between chunk streams
+/// of equal priority. Messages on the same chunk stream are never interleaved.
+#[derive(Debug, Default)]
+pub struct OutgoingScheduler {
+    pub allocator: ChunkStreamAllocator,
+    queues: HashMap<u32, VecDeque<QueuedMessage>>,
+    /// Chunk streams in the order they got their turn, least recent first.
+    rotation: VecDeque<u32>,
+}
+
+impl OutgoingScheduler {
+    pub fn enqueue(&mut self, message: RTMPMessage) {
+        let chunk_stream_id = message.chunk_stream_id;
+        let priority = MessagePriority::from_type_id(message.message_type_id);
+
+        if !self.rotation.contains(&chunk_stream_id) {
+            self.rotation.push_back(chunk_stream_id);
+        }
+
+        self.queues
+            .entry(chunk_stream_id)
+            .or_default()
+            .push_back(QueuedMessage {
+                message,
+                priority,
+                offset: 0,
+            });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.values().all(|queue| queue.is_empty())
+    }
+
+    /// Priority of the message whose chunk goes out next.
+    pub fn next_priority(&self) -> Option<MessagePriority> {
+        self.queues
+            .values()
+            .filter_map(|queue| queue.front())
+            .map(|queued| queued.priority)
+            .min()
+    }
+
+    fn next_chunk_stream_id(&self) -> Option<u32> {
+        let priority = self.next_priority()?;
+
+        self.rotation.iter().copied().find(|chunk_stream_id| {
+            self.queues
+                .get(chunk_stream_id)
+                .and_then(|queue| queue.front())
+                .is_some_and(|queued| queued.priority == priority)
+        })
+    }
+
+    /// Serializes the next chunk into `buffer`, returns false once every
+    /// queue is empty. A SetChunkSize message switches `chunk_size` once its
+    /// last chunk is written, right where the peer switches too.
+    pub fn write_next_chunk(&mut self, buffer: &mut Vec<u8>, chunk_size: &mut u32) -> bool {
+        let chunk_stream_id = match self.next_chunk_stream_id() {
+            Some(chunk_stream_id) => chunk_stream_id,
+            None => return false,
+        };
+
+        let queue = self.queues.get_mut(&chunk_stream_id).unwrap();
+        let queued = queue.front_mut().unwrap();
+
+        queued.offset += RTMPChunker::write_chunk(buffer, &queued.message, queued.offset, *chunk_size);
+
+        if queued.offset >= queued.message.payload.len() {
+            let sent = queue.pop_front().unwrap().message;
+
+            if sent.message_type_id == MessageTypeId::SetChunkSize as u8 {
+                if let Ok(size) = <[u8; 4]>::try_from(sent.payload.as_slice()) {
+                    *chunk_size = u32::from_be_bytes(size) & MAX_CHUNK_SIZE;
+                }
+            }
+        }
+
+        if queue.is_empty() {
+            self.queues.remove(&chunk_stream_id);
+        }
+
+        self.rotation.retain(|id| *id != chunk_stream_id);
+        if self.queues.contains_key(&chunk_stream_id) {
+            self.rotation.push_back(chunk_stream_id);
+        }
+
+        true
+    }
+
+    /// Sends every queued message.
+    pub fn flush<T: Transport>(&mut self, transport: &mut T, chunk_size: &mut u32) -> std::io::Result<()> {
+        self.flush_chunks(transport, chunk_size, MessagePriority::Video, usize::MAX)
+    }
+
+    /// Sends at most `max_chunks` chunks, stopping at the first one with a
+    /// lower priority than `priority`.
+    pub fn flush_chunks<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        chunk_size: &mut u32,
+        priority: MessagePriority,
+        max_chunks: usize,
+    ) -> std::io::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut chunks = 0;
+
+        while chunks < max_chunks
+            && self.next_priority().is_some_and(|next_priority| next_priority <= priority)
+            && self.write_next_chunk(&mut buffer, chunk_size)
+        {
+            chunks += 1;
+        }
+
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        transport.write_data(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(message_type_id: MessageTypeId, chunk_stream_id: u32, size: usize) -> RTMPMessage {
+        RTMPMessage {
+            timestamp: 0,
+            message_type_id: message_type_id as u8,
+            message_stream_id: 1,
+            chunk_stream_id,
+            payload: vec![0; size],
+        }
+    }
+
+    #[test]
+    fn test_allocate_chunk_stream_ids() {
+        let mut allocator = ChunkStreamAllocator::default();
+
+        assert_eq!(allocator.chunk_stream_id(0, MessagePriority::Control).unwrap(), 2);
+        assert_eq!(allocator.chunk_stream_id(0, MessagePriority::Command).unwrap(), 3);
+        assert_eq!(allocator.chunk_stream_id(1, MessagePriority::Control).unwrap(), 2);
+        assert_eq!(allocator.chunk_stream_id(1, MessagePriority::Video).unwrap(), 7);
+        assert_eq!(allocator.chunk_stream_id(1, MessagePriority::Audio).unwrap(), 8);
+        assert_eq!(allocator.chunk_stream_id(1, MessagePriority::Video).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_control_goes_before_video() {
+        let mut scheduler = OutgoingScheduler::default();
+        scheduler.enqueue(message(MessageTypeId::VideoData, 7, 300));
+        scheduler.enqueue(message(MessageTypeId::UserControlMessage, 2, 6));
+
+        let mut buffer = Vec::new();
+        assert!(scheduler.write_next_chunk(&mut buffer, &mut 128));
+
+        // basic header of chunk stream 2 with a type 0 message header
+        assert_eq!(buffer[0], 2);
+        assert_eq!(buffer[7], MessageTypeId::UserControlMessage as u8);
+    }
+
+    #[test]
+    fn test_interleave_equal_priorities() {
+        let mut scheduler = OutgoingScheduler::default();
+        scheduler.enqueue(message(MessageTypeId::VideoData, 7, 256));
+        scheduler.enqueue(message(MessageTypeId::VideoData, 9, 256));
+
+        let mut first_bytes = Vec::new();
+        loop {
+            let mut buffer = Vec::new();
+            if !scheduler.write_next_chunk(&mut buffer, &mut 128) {
+                break;
+            }
+            first_bytes.push(buffer[0]);
+        }
+
+        // type 0 on 7, type 0 on 9, then type 3 continuations alternating
+        assert_eq!(first_bytes, vec![7, 9, 0xC0 | 7, 0xC0 | 9]);
+    }
+
+    #[test]
+    fn test_three_byte_chunk_stream_id() {
+        let mut scheduler = OutgoingScheduler::default();
+        scheduler.enqueue(message(MessageTypeId::VideoData, 64 + 256 + 2, 1));
+
+        let mut buffer = Vec::new();
+        scheduler.write_next_chunk(&mut buffer, &mut 128);
+
+        assert_eq!(&buffer[..3], &[1, 2, 1]);
+    }
+}
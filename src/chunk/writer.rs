@@ -1,4 +1,4 @@
-use crate::{chunk::packets::{ChunkBasicHeader, ChunkMessageHeader, RTMPChunk}, context::NetConnectionContext, transport::Transport};
+use crate::{chunk::packets::{ChunkBasicHeader, ChunkMessageHeader, ExtendedTimestamp, RTMPChunk, EXTENDED_TIMESTAMP_MARKER}, context::NetConnectionContext, transport::Transport};
 use crate::net_connection::packets::RTMPMessage;
 
 pub struct RTMPChunker {}
@@ -8,10 +8,11 @@ impl RTMPChunker {
         let format = basic_header.chunk_header_format;
         let chunk_stream_id = basic_header.chunk_stream_id;
 
-        if chunk_stream_id >= 64 + 255 {
+        if chunk_stream_id >= 64 + 256 {
+            // 3 byte form, the id minus 64 follows in little endian
             buffer.push(format << 6 | 1);
-            buffer.push(((chunk_stream_id - 64) >> 8) as u8);
             buffer.push((chunk_stream_id - 64) as u8);
+            buffer.push(((chunk_stream_id - 64) >> 8) as u8);
         } else if chunk_stream_id >= 64 {
             buffer.push(format << 6);
             buffer.push((chunk_stream_id - 64) as u8);
@@ -39,7 +40,7 @@ impl RTMPChunker {
                 buffer.push((message_length >> 8) as u8);
                 buffer.push(message_length as u8);  
 
-                buffer.push(message_type_id as u8);
+                buffer.push(message_type_id);
 
                 // uses little endian
                 buffer.extend_from_slice(&message_stream_id.to_le_bytes());
@@ -57,7 +58,7 @@ impl RTMPChunker {
                 buffer.push((message_length >> 8) as u8);
                 buffer.push(message_length as u8);
 
-                buffer.push(message_type_id as u8);
+                buffer.push(message_type_id);
             },
             ChunkMessageHeader::Type2 { 
                 timestamp_delta 
@@ -72,56 +73,68 @@ impl RTMPChunker {
         }
     } 
 
+    /// Serializes the chunk of `rtmp_message` starting at `offset` of its
+    /// payload and returns how many payload bytes it carried. The first chunk
+    /// of a message gets a full header, the following ones continue it.
+    pub fn write_chunk(buffer: &mut Vec<u8>, rtmp_message: &RTMPMessage, offset: usize, chunk_size: u32) -> usize {
+        let end = std::cmp::min(offset + chunk_size as usize, rtmp_message.payload.len());
+
+        // timestamps that don't fit in 24 bits go in an extended timestamp,
+        // which is repeated on every continuation chunk of the message
+        let extended_timestamp = if rtmp_message.timestamp >= EXTENDED_TIMESTAMP_MARKER {
+            Some(ExtendedTimestamp(rtmp_message.timestamp))
+        } else {
+            None
+        };
+
+        let (message_header, chunk_header_format) = if offset == 0 {
+            (ChunkMessageHeader::Type0 {
+                absolute_timestamp: std::cmp::min(rtmp_message.timestamp, EXTENDED_TIMESTAMP_MARKER),
+                message_length: rtmp_message.payload.len() as u32,
+                message_type_id: rtmp_message.message_type_id,
+                message_stream_id: rtmp_message.message_stream_id
+            }, 0)
+        } else {
+            (ChunkMessageHeader::Type3, 3)
+        };
+
+        let rtmp_chunk = RTMPChunk {
+            basic_header: ChunkBasicHeader {
+                chunk_header_format,
+                chunk_stream_id: rtmp_message.chunk_stream_id
+            },
+            message_header,
+            extended_timestamp,
+            data: rtmp_message.payload[offset..end].to_vec()
+        };
+
+        RTMPChunker::write_basic_header(buffer, rtmp_chunk.basic_header);
+        RTMPChunker::write_message_header(buffer, rtmp_chunk.message_header);
+
+        if let Some(extended_timestamp) = rtmp_chunk.extended_timestamp {
+            buffer.extend_from_slice(&extended_timestamp.0.to_be_bytes());
+        }
+
+        buffer.extend_from_slice(&rtmp_chunk.data);
+
+        end - offset
+    }
+
+    /// Writes a whole message at once, bypassing the outgoing scheduler.
     pub fn write_chunks<T: Transport>(rtmp_message: RTMPMessage, context: &mut NetConnectionContext<T>) -> std::io::Result<()> {
         let mut buffer: Vec<u8> = Vec::new();
-        let mut rtmp_chunks: Vec<RTMPChunk> = Vec::new();
-
-        let payload_chunks = rtmp_message.payload.chunks(context.chunk_size as usize);
-        let mut remaining = rtmp_message.payload.len();
-    
-        let mut first_chunk = true;
-        let chunk_stream_id = rtmp_message.chunk_stream_id as u32;
-
-        for payload_chunk in payload_chunks {
-            let (chunk_header, chunk_header_format) = if first_chunk {
-                first_chunk = false;
-                (ChunkMessageHeader::Type0 {
-                    absolute_timestamp: 0, 
-                    message_length: remaining as u32, 
-                    message_type_id: rtmp_message.message_type_id, 
-                    message_stream_id: rtmp_message.message_stream_id 
-                }, 0)
-            } else {
-                (ChunkMessageHeader::Type3, 3)
-            };
-
-            remaining -= payload_chunk.len();
-            rtmp_chunks.push(RTMPChunk {
-                basic_header: ChunkBasicHeader { 
-                    chunk_header_format,
-                    chunk_stream_id
-                },
-                message_header: chunk_header,
-                extended_timestamp: None,
-                data: payload_chunk.to_vec()
-            })
-        }
+        let mut offset = 0;
 
-        for rtmp_chunk in rtmp_chunks {
-            RTMPChunker::write_basic_header(&mut buffer, rtmp_chunk.basic_header);
-            RTMPChunker::write_message_header(&mut buffer, rtmp_chunk.message_header);
+        loop {
+            offset += RTMPChunker::write_chunk(&mut buffer, &rtmp_message, offset, context.out_chunk_size);
 
-            if let Some(_extended_timestamp) = rtmp_chunk.extended_timestamp {
-                // TODO: write extended timestamp
+            if offset >= rtmp_message.payload.len() {
+                break;
             }
-
-            buffer.extend_from_slice(&rtmp_chunk.data);
         }
 
         context.transport.write_data(buffer)?;
 
         Ok(())
     }
-
-    
-}
\ No newline at end of file
+}
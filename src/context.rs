@@ -1,61 +1,155 @@
+use crate::chunk::limits::ChunkLimits;
+use crate::chunk::reader::InboundChunkStream;
+use crate::chunk::scheduler::OutgoingScheduler;
+use crate::net_connection::bandwidth::BandwidthCheck;
+use crate::net_connection::connect_options::ConnectOptions;
+use crate::net_connection::events::NetConnectionEvent;
+use crate::net_connection::handlers::HandlerRegistry;
+use crate::net_connection::packets::RTMPMessage;
+use crate::net_connection::reconnect::ReconnectPolicy;
+use crate::net_connection::state::ConnectionState;
 use crate::net_connection::transaction_manager::TransactionManager;
+use crate::net_stream::NetStream;
 use crate::shared_object::SharedObject;
 use crate::transport::Transport;
-use flash_lso::types::Value;
-use std::collections::HashMap;
+use flash_lso::types::{Element, Value};
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-#[derive(Debug, Clone)]
+/// Chunk size both peers start with until a SetChunkSize message is received.
+pub const DEFAULT_CHUNK_SIZE: u32 = 128;
+
+/// Largest chunk size allowed by the protocol, the most significant bit must be zero.
+pub const MAX_CHUNK_SIZE: u32 = 0x7FFFFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ObjectEncoding {
     AMF0 = 0,
     AMF3 = 3,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConnectionArgs {
     pub app: String,
     pub flash_ver: String,
     pub swf_url: String,
     pub tc_url: String,
     pub fpad: bool,
+    pub capabilities: u32,
     pub audio_codecs: u32,
     pub video_codecs: u32,
     pub video_function: u32,
     pub page_url: String,
     pub object_encoding: ObjectEncoding,
     pub additional_args: Vec<Value>,
+    pub extra_properties: Vec<Element>,
 }
 
 #[derive(Debug)]
 pub struct NetConnectionContext<T: Transport> {
     pub transport: T,
 
+    pub state: ConnectionState,
     pub transaction_manager: TransactionManager,
+    /// Handlers for commands the server calls on us.
+    pub handlers: HandlerRegistry,
+    pub bandwidth_check: BandwidthCheck,
     pub connection_args: Option<ConnectionArgs>,
+    pub connect_transaction_id: Option<u32>,
+    /// tcUrl and options of the last connect, reused when reconnecting.
+    pub connect_request: Option<(String, ConnectOptions)>,
+    /// tcUrl we ended up connected to, after redirects.
+    pub tc_url: Option<String>,
+    /// When the endpoint being connected to is given up on.
+    pub connect_deadline: Option<Instant>,
+    pub reconnect_policy: Option<ReconnectPolicy>,
+    pub reconnecting: bool,
+    /// Set when the server asked us to reconnect, with the tcUrl it gave if any.
+    pub reconnect_requested: Option<Option<String>>,
+    /// Encoding requested in the next connect command.
+    pub requested_object_encoding: ObjectEncoding,
+    /// Encoding agreed on with the server, used for the commands we send.
+    pub object_encoding: ObjectEncoding,
 
     pub shared_objects: HashMap<String, Arc<Mutex<SharedObject>>>,
+    pub net_streams: Vec<Arc<Mutex<NetStream>>>,
 
     pub last_ping_sent: Option<u32>,
-    pub chunk_size: u32,
+    /// Chunk size announced by the peer, used to split incoming chunks.
+    pub in_chunk_size: u32,
+    /// Chunk size we announced to the peer, used to split outgoing messages.
+    pub out_chunk_size: u32,
     pub window_ack_size: Option<u32>,
-    pub relative_timestamp: u32,
+
+    pub chunk_limits: ChunkLimits,
+    pub inbound_chunk_streams: HashMap<u32, InboundChunkStream>,
+    /// Payload bytes currently held by partially received messages.
+    pub buffered_bytes: usize,
+
+    pub outgoing: OutgoingScheduler,
+
+    /// Messages split off an aggregate that haven't been handed out yet.
+    pub pending_messages: VecDeque<RTMPMessage>,
+
+    /// Events waiting to be returned by poll_event, bounded by MAX_QUEUED_EVENTS.
+    pub events: VecDeque<NetConnectionEvent>,
 }
 
 pub fn allocate_net_connection_context<T: Transport>(transport: T) -> NetConnectionContext<T> {
     NetConnectionContext {
         transport,
+        state: ConnectionState::Disconnected,
         transaction_manager: TransactionManager::new(),
+        handlers: HandlerRegistry::new(),
+        bandwidth_check: BandwidthCheck::new(),
         connection_args: None,
+        connect_transaction_id: None,
+        connect_request: None,
+        tc_url: None,
+        connect_deadline: None,
+        reconnect_policy: None,
+        reconnecting: false,
+        reconnect_requested: None,
+        requested_object_encoding: ObjectEncoding::AMF0,
+        object_encoding: ObjectEncoding::AMF0,
 
         shared_objects: HashMap::new(),
+        net_streams: Vec::new(),
         last_ping_sent: None,
-        chunk_size: 128,
+        in_chunk_size: DEFAULT_CHUNK_SIZE,
+        out_chunk_size: DEFAULT_CHUNK_SIZE,
         window_ack_size: None,
-        relative_timestamp: 0,
+
+        chunk_limits: ChunkLimits::default(),
+        inbound_chunk_streams: HashMap::new(),
+        buffered_bytes: 0,
+
+        outgoing: OutgoingScheduler::default(),
+
+        pending_messages: VecDeque::new(),
+
+        events: VecDeque::new(),
     }
 }
 
 impl<T: Transport> NetConnectionContext<T> {
+    /// Forgets everything tied to the current transport connection, before
+    /// connecting again.
+    pub fn reset_session(&mut self) {
+        self.connect_transaction_id = None;
+        self.bandwidth_check.reset();
+        self.object_encoding = ObjectEncoding::AMF0;
+        self.last_ping_sent = None;
+        self.in_chunk_size = DEFAULT_CHUNK_SIZE;
+        self.out_chunk_size = DEFAULT_CHUNK_SIZE;
+        self.window_ack_size = None;
+        self.inbound_chunk_streams.clear();
+        self.buffered_bytes = 0;
+        self.outgoing = OutgoingScheduler::default();
+        self.pending_messages.clear();
+    }
+
     pub fn get_shared_object(&self, name: &str) -> std::io::Result<Arc<Mutex<SharedObject>>> {
         self.shared_objects.get(name).cloned().ok_or(std::io::Error::new(
             std::io::ErrorKind::NotFound,
@@ -78,4 +172,23 @@ impl<T: Transport> NetConnectionContext<T> {
     pub fn has_shared_object(&self, name: &str) -> bool {
         self.shared_objects.contains_key(name)
     }
+
+    pub fn get_net_stream(&self, stream_id: u32) -> Option<Arc<Mutex<NetStream>>> {
+        self.net_streams
+            .iter()
+            .find(|net_stream| net_stream.lock().unwrap().stream_id == Some(stream_id))
+            .cloned()
+    }
+
+    /// Adds a stream once, creating it again after a reconnect keeps its place.
+    pub fn add_net_stream(&mut self, net_stream: Arc<Mutex<NetStream>>) {
+        if !self.net_streams.iter().any(|known| Arc::ptr_eq(known, &net_stream)) {
+            self.net_streams.push(net_stream);
+        }
+    }
+
+    pub fn remove_net_stream(&mut self, stream_id: u32) {
+        self.net_streams
+            .retain(|net_stream| net_stream.lock().unwrap().stream_id != Some(stream_id));
+    }
 }
\ No newline at end of file
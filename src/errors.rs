@@ -1,6 +1,8 @@
 use nom::error::{ErrorKind, FromExternalError, ParseError};
 use thiserror::Error;
 
+use crate::net_connection::state::ConnectionState;
+
 
 // Allow the Nom variant to be large
 #[allow(variant_size_differences)]
@@ -31,4 +33,44 @@ impl<'a, E> FromExternalError<&'a [u8], E> for Error<'a> {
     fn from_external_error(input: &'a [u8], kind: ErrorKind, _e: E) -> Self {
         Error::Nom(input, kind)
     }
-}
\ No newline at end of file
+}
+
+/// Violations of the protocol or of the configured resource limits by the peer
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ProtocolError {
+    #[error("Message of {size} bytes exceeds the limit of {limit} bytes")]
+    MessageTooLarge { size: u32, limit: u32 },
+
+    #[error("Chunk size of {size} bytes exceeds the limit of {limit} bytes")]
+    ChunkSizeTooLarge { size: u32, limit: u32 },
+
+    #[error("More than {limit} partially received messages")]
+    TooManyPartialMessages { limit: usize },
+
+    #[error("Buffering {size} bytes exceeds the limit of {limit} bytes")]
+    BufferLimitExceeded { size: usize, limit: usize },
+
+    #[error("Chunk stream {0} continues a message without a preceding header")]
+    MissingChunkHeader(u32),
+
+    #[error("More than {limit} calls waiting for an answer")]
+    TooManyPendingTransactions { limit: usize },
+
+    #[error("Connect was redirected more than {limit} times")]
+    TooManyRedirects { limit: u32 },
+
+    #[error("Connect was redirected back to {0}")]
+    RedirectLoop(String),
+
+    #[error("Protocol {0} isn't supported, only rtmp is")]
+    UnsupportedProtocol(String),
+
+    #[error("Connection can't go from {from:?} to {to:?}")]
+    InvalidStateTransition { from: ConnectionState, to: ConnectionState },
+}
+
+impl From<ProtocolError> for std::io::Error {
+    fn from(error: ProtocolError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
@@ -0,0 +1,5 @@
+pub mod packets;
+pub mod publisher;
+pub mod reader;
+pub mod recorder;
+pub mod writer;
@@ -0,0 +1,74 @@
+use crate::chunk::packets::MessageTypeId;
+
+pub const FLV_SIGNATURE: &[u8; 3] = b"FLV";
+pub const FLV_HEADER_SIZE: u32 = 9;
+pub const FLV_TAG_HEADER_SIZE: u32 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlvHeader {
+    pub version: u8,
+    pub has_audio: bool,
+    pub has_video: bool,
+    /// Offset of the first previous-tag-size field, 9 for version 1
+    pub data_offset: u32,
+}
+
+impl Default for FlvHeader {
+    fn default() -> Self {
+        FlvHeader {
+            version: 1,
+            has_audio: true,
+            has_video: true,
+            data_offset: FLV_HEADER_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlvTagType {
+    Audio = 8,
+    Video = 9,
+    ScriptData = 18,
+}
+
+impl TryFrom<u8> for FlvTagType {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            8 => Ok(FlvTagType::Audio),
+            9 => Ok(FlvTagType::Video),
+            18 => Ok(FlvTagType::ScriptData),
+            _ => Err("Invalid FLV tag type"),
+        }
+    }
+}
+
+impl From<FlvTagType> for MessageTypeId {
+    fn from(value: FlvTagType) -> Self {
+        match value {
+            FlvTagType::Audio => MessageTypeId::AudioData,
+            FlvTagType::Video => MessageTypeId::VideoData,
+            FlvTagType::ScriptData => MessageTypeId::DataAMF0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlvTagHeader {
+    pub tag_type: FlvTagType,
+    /// Set when the tag body is encrypted
+    pub filtered: bool,
+    pub data_size: u32,
+    /// Milliseconds, including the extended upper byte
+    pub timestamp: u32,
+    pub stream_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlvTag {
+    pub tag_type: FlvTagType,
+    pub timestamp: u32,
+    /// Tag body, the same bytes as the payload of the matching RTMP message
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,213 @@
+use std::io::{Read, Seek};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use flash_lso::types::Value;
+
+use crate::chunk::packets::MessageTypeId;
+use crate::context::ObjectEncoding;
+use crate::flv::packets::{FlvTag, FlvTagType};
+use crate::flv::reader::FlvReader;
+use crate::media::reader::MediaReader;
+use crate::net_connection::data_messages::{ON_META_DATA, SET_DATA_FRAME};
+use crate::net_connection::packets::{RTMPMessageHeader, RTMPMessageType};
+use crate::net_connection::reader::RTMPReader;
+use crate::net_connection::NetConnection;
+use crate::net_stream::NetStream;
+use crate::transport::Transport;
+
+/// Sends the tags of an FLV file on a `NetStream` that is publishing.
+pub struct FlvPublisher<R: Read + Seek> {
+    reader: FlvReader<R>,
+    /// Wait for each tag's timestamp instead of sending as fast as possible
+    pub realtime: bool,
+    /// Start over at the end of the file, timestamps keep increasing
+    pub looping: bool,
+
+    timestamp_offset: u32,
+    last_timestamp: u32,
+    started: Option<(Instant, u32)>,
+}
+
+impl<R: Read + Seek> FlvPublisher<R> {
+    pub fn new(reader: FlvReader<R>) -> Self {
+        FlvPublisher {
+            reader,
+            realtime: false,
+            looping: false,
+            timestamp_offset: 0,
+            last_timestamp: 0,
+            started: None,
+        }
+    }
+
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Turns a tag into the message it is sent as. onMetaData becomes
+    /// @setDataFrame so the server keeps the metadata for its players, other
+    /// script data like cue points keeps its handler.
+    pub fn tag_to_message(tag: FlvTag) -> std::io::Result<RTMPMessageType> {
+        let message = match tag.tag_type {
+            FlvTagType::Audio => MediaReader::read_audio(&tag.data).map(|(_, audio)| RTMPMessageType::Audio(audio)).ok(),
+            FlvTagType::Video => MediaReader::read_video(&tag.data).map(|(_, video)| RTMPMessageType::Video(video)).ok(),
+            FlvTagType::ScriptData => RTMPReader::read_amf0_data(&tag.data, ObjectEncoding::AMF0)
+                .ok()
+                .map(|mut data| {
+                    if data.handler == ON_META_DATA {
+                        data.arguments.insert(0, Value::String(data.handler));
+                        data.handler = SET_DATA_FRAME.to_string();
+                    }
+
+                    RTMPMessageType::Data(data)
+                }),
+        };
+
+        // tags we can't parse are still sent, byte for byte
+        Ok(message.unwrap_or(RTMPMessageType::Raw {
+            type_id: MessageTypeId::from(tag.tag_type) as u8,
+            stream_id: 0,
+            timestamp: tag.timestamp,
+            payload: tag.data,
+        }))
+    }
+
+    fn wait_for(&mut self, timestamp: u32) {
+        let (start, start_timestamp) = *self.started.get_or_insert((Instant::now(), timestamp));
+
+        let due = start + Duration::from_millis(timestamp.wrapping_sub(start_timestamp) as u64);
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+    }
+
+    /// Sends the next tag, returns false once the file is done. Calling this
+    /// in a loop lets the caller process incoming messages in between.
+    pub fn send_next_tag<T: Transport>(
+        &mut self,
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<bool> {
+        let tag = match self.reader.next_tag()? {
+            Some(tag) => tag,
+            None if self.looping => {
+                self.reader.rewind()?;
+                self.timestamp_offset = self.last_timestamp;
+
+                match self.reader.next_tag()? {
+                    Some(tag) => tag,
+                    None => return Ok(false),
+                }
+            }
+            None => return Ok(false),
+        };
+
+        let timestamp = tag.timestamp.wrapping_add(self.timestamp_offset);
+        self.last_timestamp = timestamp;
+
+        if self.realtime {
+            self.wait_for(timestamp);
+        }
+
+        let stream_id = net_stream.lock().unwrap().stream_id.ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotConnected,
+            "NetStream hasn't been created yet",
+        ))?;
+
+        let message = match FlvPublisher::<R>::tag_to_message(tag)? {
+            RTMPMessageType::Raw { type_id, payload, .. } => RTMPMessageType::Raw {
+                type_id,
+                stream_id,
+                timestamp,
+                payload,
+            },
+            message => message,
+        };
+
+        connection.send_message(
+            message,
+            RTMPMessageHeader {
+                timestamp,
+                message_stream_id: stream_id,
+            },
+        )?;
+
+        Ok(true)
+    }
+
+    /// Sends the whole file, forever when looping.
+    pub fn publish<T: Transport>(
+        &mut self,
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<()> {
+        while self.send_next_tag(net_stream.clone(), connection)? {}
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net_connection::packets::DataMessage;
+    use crate::net_connection::writer::RTMPWriter;
+
+    fn script_tag(handler: &str) -> FlvTag {
+        let mut data = Vec::new();
+        RTMPWriter::write_data(
+            DataMessage {
+                handler: handler.to_string(),
+                arguments: vec![Value::Number(1.0)],
+                object_encoding: ObjectEncoding::AMF0,
+            },
+            &mut data,
+        )
+        .unwrap();
+
+        FlvTag {
+            tag_type: FlvTagType::ScriptData,
+            timestamp: 0,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_script_tags() {
+        match FlvPublisher::<std::io::Cursor<Vec<u8>>>::tag_to_message(script_tag("onMetaData")).unwrap() {
+            RTMPMessageType::Data(data) => {
+                assert_eq!(data.handler, SET_DATA_FRAME);
+                assert_eq!(data.arguments[0], Value::String(ON_META_DATA.to_string()));
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+
+        match FlvPublisher::<std::io::Cursor<Vec<u8>>>::tag_to_message(script_tag("onCuePoint")).unwrap() {
+            RTMPMessageType::Data(data) => assert_eq!(data.handler, "onCuePoint"),
+            message => panic!("unexpected message {:?}", message),
+        }
+
+        // broken script data still goes out untouched
+        let tag = FlvTag {
+            tag_type: FlvTagType::ScriptData,
+            timestamp: 40,
+            data: vec![0xff, 0x00],
+        };
+        match FlvPublisher::<std::io::Cursor<Vec<u8>>>::tag_to_message(tag).unwrap() {
+            RTMPMessageType::Raw { type_id, timestamp, payload, .. } => {
+                assert_eq!(type_id, MessageTypeId::DataAMF0 as u8);
+                assert_eq!(timestamp, 40);
+                assert_eq!(payload, vec![0xff, 0x00]);
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use nom::bytes::complete::tag;
+use nom::number::complete::{be_u24, be_u32, be_u8};
+
+use crate::errors::Error;
+use crate::flv::packets::{FlvHeader, FlvTag, FlvTagHeader, FlvTagType, FLV_HEADER_SIZE, FLV_SIGNATURE, FLV_TAG_HEADER_SIZE};
+use crate::utils::nom::RTMPResult;
+
+/// Demuxes an FLV file tag by tag.
+pub struct FlvReader<R: Read> {
+    reader: R,
+    pub header: FlvHeader,
+}
+
+impl<R: Read> FlvReader<R> {
+    pub fn read_header(i: &[u8]) -> RTMPResult<'_, FlvHeader> {
+        let (i, _) = tag(FLV_SIGNATURE)(i)?;
+        let (i, version) = be_u8(i)?;
+        let (i, flags) = be_u8(i)?;
+        let (i, data_offset) = be_u32(i)?;
+
+        Ok((i, FlvHeader {
+            version,
+            has_audio: flags & 0x04 != 0,
+            has_video: flags & 0x01 != 0,
+            data_offset,
+        }))
+    }
+
+    pub fn read_tag_header(i: &[u8]) -> RTMPResult<'_, FlvTagHeader> {
+        let (i, flags) = be_u8(i)?;
+        let (i, data_size) = be_u24(i)?;
+        let (i, timestamp) = be_u24(i)?;
+        let (i, timestamp_extended) = be_u8(i)?;
+        let (i, stream_id) = be_u24(i)?;
+
+        let tag_type = FlvTagType::try_from(flags & 0x1F).map_err(|e| {
+            nom::Err::Failure(Error::IoError(e.to_string(), std::io::ErrorKind::InvalidData))
+        })?;
+
+        Ok((i, FlvTagHeader {
+            tag_type,
+            filtered: flags & 0x20 != 0,
+            data_size,
+            timestamp: (timestamp_extended as u32) << 24 | timestamp,
+            stream_id,
+        }))
+    }
+
+    /// Reads the file header and the first previous-tag-size.
+    pub fn new(mut reader: R) -> std::io::Result<Self> {
+        let mut header_bytes = [0u8; FLV_HEADER_SIZE as usize];
+        reader.read_exact(&mut header_bytes)?;
+
+        let (_, header) = FlvReader::<R>::read_header(&header_bytes).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Not an FLV file")
+        })?;
+
+        if header.data_offset < FLV_HEADER_SIZE {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid FLV data offset"));
+        }
+
+        // skip whatever a future header version puts after the known fields
+        let extra_header = (header.data_offset - FLV_HEADER_SIZE) as u64;
+        std::io::copy(&mut (&mut reader).take(extra_header), &mut std::io::sink())?;
+
+        let mut flv_reader = FlvReader { reader, header };
+        flv_reader.read_previous_tag_size(0)?;
+
+        Ok(flv_reader)
+    }
+
+    fn read_previous_tag_size(&mut self, expected: u32) -> std::io::Result<()> {
+        let mut size_bytes = [0u8; 4];
+        self.reader.read_exact(&mut size_bytes)?;
+
+        let previous_tag_size = u32::from_be_bytes(size_bytes);
+        if previous_tag_size != expected {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Previous tag size is {}, expected {}", previous_tag_size, expected),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next tag, or `None` at the end of the file.
+    pub fn next_tag(&mut self) -> std::io::Result<Option<FlvTag>> {
+        let mut header_bytes = [0u8; FLV_TAG_HEADER_SIZE as usize];
+
+        // a clean end of file can only happen between two tags
+        let read = self.reader.read(&mut header_bytes)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut header_bytes[read..])?;
+
+        let (_, tag_header) = FlvReader::<R>::read_tag_header(&header_bytes).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid FLV tag header: {:?}", e))
+        })?;
+
+        let mut data = vec![0u8; tag_header.data_size as usize];
+        self.reader.read_exact(&mut data)?;
+        self.read_previous_tag_size(FLV_TAG_HEADER_SIZE + tag_header.data_size)?;
+
+        Ok(Some(FlvTag {
+            tag_type: tag_header.tag_type,
+            timestamp: tag_header.timestamp,
+            data,
+        }))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> FlvReader<R> {
+    /// Goes back to the first tag.
+    pub fn rewind(&mut self) -> std::io::Result<()> {
+        self.reader.seek(SeekFrom::Start(self.header.data_offset as u64 + 4))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn flv_file(previous_tag_size: u32) -> Vec<u8> {
+        let mut file = vec![b'F', b'L', b'V', 1, 0x05, 0, 0, 0, 9, 0, 0, 0, 0];
+        // audio tag, 2 bytes, timestamp 0x01000010
+        file.extend_from_slice(&[8, 0, 0, 2, 0, 0, 0x10, 0x01, 0, 0, 0, 0xAF, 0x01]);
+        file.extend_from_slice(&previous_tag_size.to_be_bytes());
+        file
+    }
+
+    #[test]
+    fn test_read_tags() {
+        let mut reader = FlvReader::new(Cursor::new(flv_file(13))).unwrap();
+        assert!(reader.header.has_audio && reader.header.has_video);
+
+        let tag = reader.next_tag().unwrap().unwrap();
+        assert_eq!(tag.tag_type, FlvTagType::Audio);
+        assert_eq!(tag.timestamp, 0x01000010);
+        assert_eq!(tag.data, vec![0xAF, 0x01]);
+        assert_eq!(reader.next_tag().unwrap(), None);
+
+        reader.rewind().unwrap();
+        assert!(reader.next_tag().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_invalid_previous_tag_size() {
+        let mut reader = FlvReader::new(Cursor::new(flv_file(12))).unwrap();
+        let error = reader.next_tag().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,371 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use flash_lso::types::{Element, Value};
+
+use crate::chunk::packets::MessageTypeId;
+use crate::context::ObjectEncoding;
+use crate::flv::packets::{FlvHeader, FlvTag, FlvTagType};
+use crate::flv::writer::FlvWriter;
+use crate::net_connection::data_messages::{MetaData, RTMP_SAMPLE_ACCESS};
+use crate::net_connection::packets::{DataMessage, RTMPMessageType};
+use crate::net_connection::writer::RTMPWriter;
+use crate::net_stream::NetStreamEvent;
+
+const KEYFRAMES: &str = "keyframes";
+const SHIFT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Writes received audio, video and data messages to an FLV file. The
+/// metadata is rewritten with duration, file size and a keyframe index when
+/// the recording is closed.
+pub struct FlvRecorder<W: Read + Write + Seek> {
+    writer: W,
+    position: u64,
+
+    meta_data: MetaData,
+    /// Start and size of the metadata tag, once written
+    meta_data_tag: Option<(u64, u64)>,
+
+    base_timestamp: Option<u32>,
+    last_timestamp: u32,
+    /// Time in seconds and file position of every video keyframe
+    keyframes: Vec<(f64, u64)>,
+}
+
+impl<W: Read + Write + Seek> FlvRecorder<W> {
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        let mut payload_vector = Vec::new();
+        FlvWriter::write_header(&FlvHeader::default(), &mut payload_vector);
+        writer.write_all(&payload_vector)?;
+
+        Ok(FlvRecorder {
+            writer,
+            position: payload_vector.len() as u64,
+            meta_data: MetaData::default(),
+            meta_data_tag: None,
+            base_timestamp: None,
+            last_timestamp: 0,
+            keyframes: Vec::new(),
+        })
+    }
+
+    fn meta_data_payload(meta_data: &MetaData) -> std::io::Result<Vec<u8>> {
+        let mut payload_vector = Vec::new();
+        RTMPWriter::write_data(DataMessage::on_meta_data(meta_data), &mut payload_vector)?;
+
+        Ok(payload_vector)
+    }
+
+    fn write_tag(&mut self, tag: FlvTag) -> std::io::Result<()> {
+        let mut payload_vector = Vec::with_capacity(tag.data.len() + 15);
+        FlvWriter::write_tag(&tag, &mut payload_vector);
+
+        self.writer.write_all(&payload_vector)?;
+        self.position += payload_vector.len() as u64;
+
+        Ok(())
+    }
+
+    /// The metadata goes first, written as soon as the first media tag comes in.
+    fn write_meta_data_tag(&mut self) -> std::io::Result<()> {
+        if self.meta_data_tag.is_some() {
+            return Ok(());
+        }
+
+        // duration and filesize are reserved now so the final tag only grows
+        let mut meta_data = self.meta_data.clone();
+        meta_data.duration = Some(0.0);
+        meta_data.file_size = Some(0.0);
+        meta_data.extra.retain(|element| element.name != KEYFRAMES);
+
+        let start = self.position;
+        self.write_tag(FlvTag {
+            tag_type: FlvTagType::ScriptData,
+            timestamp: 0,
+            data: FlvRecorder::<W>::meta_data_payload(&meta_data)?,
+        })?;
+        self.meta_data_tag = Some((start, self.position - start));
+
+        Ok(())
+    }
+
+    /// The first audio or video message starts the file at 0, script data
+    /// coming before it is put at 0 as well. So is anything stamped before
+    /// it, like audio lagging a little behind the first video frame.
+    fn rebase_timestamp(&mut self, tag_type: FlvTagType, timestamp: u32) -> u32 {
+        let base_timestamp = match (self.base_timestamp, tag_type) {
+            (Some(base_timestamp), _) => base_timestamp,
+            (None, FlvTagType::ScriptData) => return 0,
+            (None, _) => *self.base_timestamp.insert(timestamp),
+        };
+        let timestamp = timestamp.saturating_sub(base_timestamp);
+
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+        timestamp
+    }
+
+    fn write_media(&mut self, tag_type: FlvTagType, timestamp: u32, data: Vec<u8>, keyframe: bool) -> std::io::Result<()> {
+        self.write_meta_data_tag()?;
+
+        let timestamp = self.rebase_timestamp(tag_type, timestamp);
+        if keyframe {
+            self.keyframes.push((timestamp as f64 / 1000.0, self.position));
+        }
+
+        self.write_tag(FlvTag {
+            tag_type,
+            timestamp,
+            data,
+        })
+    }
+
+    fn write_data(&mut self, timestamp: u32, data: DataMessage) -> std::io::Result<()> {
+        if data.handler == RTMP_SAMPLE_ACCESS {
+            return Ok(());
+        }
+
+        if let Some(meta_data) = data.meta_data() {
+            // metadata received before any media ends up in the header tag
+            if self.meta_data_tag.is_none() {
+                self.meta_data = meta_data;
+                return Ok(());
+            }
+
+            // later ones are kept in place as plain onMetaData tags
+            let payload_vector = FlvRecorder::<W>::meta_data_payload(&meta_data)?;
+            return self.write_media(FlvTagType::ScriptData, timestamp, payload_vector, false);
+        }
+
+        let mut payload_vector = Vec::new();
+        RTMPWriter::write_data(
+            DataMessage {
+                object_encoding: ObjectEncoding::AMF0,
+                ..data
+            },
+            &mut payload_vector,
+        )?;
+
+        self.write_media(FlvTagType::ScriptData, timestamp, payload_vector, false)
+    }
+
+    /// Records an audio, video or data message, anything else is ignored.
+    pub fn write_message(&mut self, timestamp: u32, message: RTMPMessageType) -> std::io::Result<()> {
+        match message {
+            RTMPMessageType::Audio(audio) => self.write_media(FlvTagType::Audio, timestamp, audio.payload(), false),
+            RTMPMessageType::Video(video) => {
+                let keyframe = video.is_keyframe();
+                self.write_media(FlvTagType::Video, timestamp, video.payload(), keyframe)
+            }
+            RTMPMessageType::Data(data) => self.write_data(timestamp, data),
+            RTMPMessageType::Raw { type_id, payload, .. } => match MessageTypeId::try_from(type_id) {
+                Ok(MessageTypeId::AudioData) => self.write_media(FlvTagType::Audio, timestamp, payload, false),
+                Ok(MessageTypeId::VideoData) => self.write_media(FlvTagType::Video, timestamp, payload, false),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the media carried by a `NetStream` event.
+    pub fn write_event(&mut self, event: NetStreamEvent) -> std::io::Result<()> {
+        match event {
+            NetStreamEvent::Audio { timestamp, audio } => self.write_message(timestamp, RTMPMessageType::Audio(audio)),
+            NetStreamEvent::Video { timestamp, video } => self.write_message(timestamp, RTMPMessageType::Video(video)),
+            NetStreamEvent::Data(data) => {
+                // data events carry no timestamp, they go at the current position
+                let timestamp = self.base_timestamp.unwrap_or(0).wrapping_add(self.last_timestamp);
+                self.write_data(timestamp, data)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves everything from `from` to the end of the file `delta` bytes further.
+    fn shift_tail(&mut self, from: u64, delta: u64) -> std::io::Result<()> {
+        let mut buffer = vec![0u8; SHIFT_BUFFER_SIZE];
+        let mut position = self.position;
+
+        while position > from {
+            let size = (position - from).min(SHIFT_BUFFER_SIZE as u64) as usize;
+            position -= size as u64;
+
+            self.writer.seek(SeekFrom::Start(position))?;
+            self.writer.read_exact(&mut buffer[..size])?;
+            self.writer.seek(SeekFrom::Start(position + delta))?;
+            self.writer.write_all(&buffer[..size])?;
+        }
+
+        self.position += delta;
+
+        Ok(())
+    }
+
+    fn keyframes_value(&self, delta: u64) -> Value {
+        let times = self.keyframes.iter().map(|(time, _)| Rc::new(Value::Number(*time))).collect();
+        let file_positions = self
+            .keyframes
+            .iter()
+            .map(|(_, position)| Rc::new(Value::Number((position + delta) as f64)))
+            .collect();
+
+        Value::Object(
+            vec![
+                Element {
+                    name: String::from("times"),
+                    value: Rc::new(Value::StrictArray(times)),
+                },
+                Element {
+                    name: String::from("filepositions"),
+                    value: Rc::new(Value::StrictArray(file_positions)),
+                },
+            ],
+            None,
+        )
+    }
+
+    /// Rewrites the metadata with the final duration, file size and keyframe
+    /// index, then hands back the underlying writer.
+    pub fn close(mut self) -> std::io::Result<W> {
+        self.write_meta_data_tag()?;
+        let (start, old_size) = self.meta_data_tag.expect("metadata tag was just written");
+
+        let mut meta_data = self.meta_data.clone();
+        meta_data.duration = Some(self.last_timestamp as f64 / 1000.0);
+        meta_data.file_size = Some(0.0);
+        meta_data.extra.retain(|element| element.name != KEYFRAMES);
+        meta_data.extra.push(Element {
+            name: KEYFRAMES.to_string(),
+            value: Rc::new(self.keyframes_value(0)),
+        });
+
+        // numbers have a fixed size, so the placeholder tag has the final size
+        let new_size = FlvRecorder::<W>::meta_data_payload(&meta_data)?.len() as u64 + 15;
+        let delta = new_size - old_size;
+
+        self.shift_tail(start + old_size, delta)?;
+
+        meta_data.file_size = Some(self.position as f64);
+        if let Some(keyframes) = meta_data.extra.last_mut() {
+            keyframes.value = Rc::new(self.keyframes_value(delta));
+        }
+
+        let mut payload_vector = Vec::new();
+        FlvWriter::write_tag(
+            &FlvTag {
+                tag_type: FlvTagType::ScriptData,
+                timestamp: 0,
+                data: FlvRecorder::<W>::meta_data_payload(&meta_data)?,
+            },
+            &mut payload_vector,
+        );
+
+        self.writer.seek(SeekFrom::Start(start))?;
+        self.writer.write_all(&payload_vector)?;
+        self.writer.seek(SeekFrom::Start(self.position))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flv::reader::FlvReader;
+    use crate::media::reader::MediaReader;
+    use crate::utils::amf::get_property;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_record_and_index() {
+        let mut recorder = FlvRecorder::new(Cursor::new(Vec::new())).unwrap();
+
+        let meta_data = MetaData {
+            width: Some(640.0),
+            ..MetaData::default()
+        };
+        recorder
+            .write_message(1000, RTMPMessageType::Data(DataMessage::on_meta_data(&meta_data)))
+            .unwrap();
+
+        for (timestamp, payload) in [(1000, [0x17, 0x01, 0, 0, 0]), (1040, [0x27, 0x01, 0, 0, 0]), (3000, [0x17, 0x01, 0, 0, 0])] {
+            let (_, video) = MediaReader::read_video(&payload).unwrap();
+            recorder.write_message(timestamp, RTMPMessageType::Video(video)).unwrap();
+        }
+
+        let file = recorder.close().unwrap().into_inner();
+        let mut reader = FlvReader::new(Cursor::new(file.clone())).unwrap();
+
+        let tag = reader.next_tag().unwrap().unwrap();
+        assert_eq!(tag.tag_type, FlvTagType::ScriptData);
+
+        let data = crate::net_connection::reader::RTMPReader::read_amf0_data(&tag.data, ObjectEncoding::AMF0).unwrap();
+        let meta_data = data.meta_data().unwrap();
+        assert_eq!(meta_data.width, Some(640.0));
+        assert_eq!(meta_data.duration, Some(2.0));
+        assert_eq!(meta_data.file_size, Some(file.len() as f64));
+
+        let keyframes = meta_data.extra.iter().find(|element| element.name == KEYFRAMES).unwrap();
+        let positions = match get_property(&keyframes.value, "filepositions") {
+            Some(Value::StrictArray(positions)) => positions.clone(),
+            _ => panic!("missing filepositions"),
+        };
+        assert_eq!(positions.len(), 2);
+
+        for position in positions {
+            let Value::Number(position) = *position else { panic!() };
+            assert_eq!(file[position as usize], FlvTagType::Video as u8);
+            assert_eq!(file[position as usize + 11], 0x17);
+        }
+
+        let timestamps: Vec<u32> = std::iter::from_fn(|| reader.next_tag().unwrap()).map(|tag| tag.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 40, 2000]);
+    }
+
+    #[test]
+    fn test_late_meta_data() {
+        let mut recorder = FlvRecorder::new(Cursor::new(Vec::new())).unwrap();
+
+        let (_, video) = MediaReader::read_video(&[0x17, 0x01, 0, 0, 0]).unwrap();
+        recorder.write_message(1000, RTMPMessageType::Video(video)).unwrap();
+
+        let meta_data = MetaData {
+            width: Some(640.0),
+            ..MetaData::default()
+        };
+        recorder
+            .write_message(1040, RTMPMessageType::Data(DataMessage::on_meta_data(&meta_data)))
+            .unwrap();
+
+        let file = recorder.close().unwrap().into_inner();
+        let mut reader = FlvReader::new(Cursor::new(file)).unwrap();
+
+        let tags: Vec<FlvTag> = std::iter::from_fn(|| reader.next_tag().unwrap()).collect();
+        let tag_types: Vec<FlvTagType> = tags.iter().map(|tag| tag.tag_type).collect();
+        assert_eq!(tag_types, vec![FlvTagType::ScriptData, FlvTagType::Video, FlvTagType::ScriptData]);
+        assert_eq!(tags[2].timestamp, 40);
+
+        let data = crate::net_connection::reader::RTMPReader::read_amf0_data(&tags[2].data, ObjectEncoding::AMF0).unwrap();
+        assert_eq!(data.meta_data().unwrap().width, Some(640.0));
+    }
+
+    #[test]
+    fn test_audio_before_first_video() {
+        let mut recorder = FlvRecorder::new(Cursor::new(Vec::new())).unwrap();
+
+        let (_, video) = MediaReader::read_video(&[0x17, 0x01, 0, 0, 0]).unwrap();
+        recorder.write_message(1000, RTMPMessageType::Video(video)).unwrap();
+        let (_, audio) = MediaReader::read_audio(&[0xAF, 0x01, 0]).unwrap();
+        recorder.write_message(995, RTMPMessageType::Audio(audio)).unwrap();
+
+        let file = recorder.close().unwrap().into_inner();
+        let mut reader = FlvReader::new(Cursor::new(file)).unwrap();
+
+        let tag = reader.next_tag().unwrap().unwrap();
+        let data = crate::net_connection::reader::RTMPReader::read_amf0_data(&tag.data, ObjectEncoding::AMF0).unwrap();
+        assert_eq!(data.meta_data().unwrap().duration, Some(0.0));
+
+        let timestamps: Vec<u32> = std::iter::from_fn(|| reader.next_tag().unwrap()).map(|tag| tag.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 0]);
+    }
+}
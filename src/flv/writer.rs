@@ -0,0 +1,27 @@
+use crate::flv::packets::{FlvHeader, FlvTag, FLV_SIGNATURE, FLV_TAG_HEADER_SIZE};
+
+pub struct FlvWriter {}
+
+impl FlvWriter {
+    /// Writes the file header followed by the first previous-tag-size.
+    pub fn write_header(header: &FlvHeader, payload_vector: &mut Vec<u8>) {
+        payload_vector.extend_from_slice(FLV_SIGNATURE);
+        payload_vector.push(header.version);
+        payload_vector.push((header.has_audio as u8) << 2 | header.has_video as u8);
+        payload_vector.extend_from_slice(&header.data_offset.to_be_bytes());
+        payload_vector.extend_from_slice(&0u32.to_be_bytes());
+    }
+
+    /// Writes a tag followed by its previous-tag-size.
+    pub fn write_tag(tag: &FlvTag, payload_vector: &mut Vec<u8>) {
+        let data_size = tag.data.len() as u32;
+
+        payload_vector.push(tag.tag_type as u8);
+        payload_vector.extend_from_slice(&data_size.to_be_bytes()[1..]);
+        payload_vector.extend_from_slice(&tag.timestamp.to_be_bytes()[1..]);
+        payload_vector.push((tag.timestamp >> 24) as u8);
+        payload_vector.extend_from_slice(&[0, 0, 0]);
+        payload_vector.extend_from_slice(&tag.data);
+        payload_vector.extend_from_slice(&(FLV_TAG_HEADER_SIZE + data_size).to_be_bytes());
+    }
+}
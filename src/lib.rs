@@ -6,6 +6,9 @@ pub mod utils;
 pub mod transport;
 pub mod context;
 pub mod net_connection;
+pub mod net_stream;
+pub mod media;
+pub mod flv;
 pub mod shared_object;
 pub mod handshake;
 pub mod chunk;
@@ -0,0 +1,3 @@
+pub mod packets;
+pub mod reader;
+pub mod writer;
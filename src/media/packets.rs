@@ -0,0 +1,235 @@
+use crate::media::writer::MediaWriter;
+
+/// Codec of an audio message, the upper 4 bits of the FLV audio tag header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundFormat {
+    LinearPCMPlatformEndian = 0,
+    ADPCM = 1,
+    MP3 = 2,
+    LinearPCMLittleEndian = 3,
+    Nellymoser16kHzMono = 4,
+    Nellymoser8kHzMono = 5,
+    Nellymoser = 6,
+    G711ALaw = 7,
+    G711MuLaw = 8,
+    Reserved = 9,
+    AAC = 10,
+    Speex = 11,
+    MP38kHz = 14,
+    DeviceSpecific = 15,
+}
+
+impl TryFrom<u8> for SoundFormat {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SoundFormat::LinearPCMPlatformEndian),
+            1 => Ok(SoundFormat::ADPCM),
+            2 => Ok(SoundFormat::MP3),
+            3 => Ok(SoundFormat::LinearPCMLittleEndian),
+            4 => Ok(SoundFormat::Nellymoser16kHzMono),
+            5 => Ok(SoundFormat::Nellymoser8kHzMono),
+            6 => Ok(SoundFormat::Nellymoser),
+            7 => Ok(SoundFormat::G711ALaw),
+            8 => Ok(SoundFormat::G711MuLaw),
+            9 => Ok(SoundFormat::Reserved),
+            10 => Ok(SoundFormat::AAC),
+            11 => Ok(SoundFormat::Speex),
+            14 => Ok(SoundFormat::MP38kHz),
+            15 => Ok(SoundFormat::DeviceSpecific),
+            _ => Err("Invalid sound format"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundRate {
+    Rate5_5kHz = 0,
+    Rate11kHz = 1,
+    Rate22kHz = 2,
+    Rate44kHz = 3,
+}
+
+impl From<u8> for SoundRate {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0 => SoundRate::Rate5_5kHz,
+            1 => SoundRate::Rate11kHz,
+            2 => SoundRate::Rate22kHz,
+            _ => SoundRate::Rate44kHz,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundSize {
+    Bits8 = 0,
+    Bits16 = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundType {
+    Mono = 0,
+    Stereo = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AACPacketType {
+    /// AudioSpecificConfig
+    SequenceHeader = 0,
+    Raw = 1,
+}
+
+impl TryFrom<u8> for AACPacketType {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AACPacketType::SequenceHeader),
+            1 => Ok(AACPacketType::Raw),
+            _ => Err("Invalid AAC packet type"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioTagHeader {
+    pub sound_format: SoundFormat,
+    pub sound_rate: SoundRate,
+    pub sound_size: SoundSize,
+    pub sound_type: SoundType,
+    /// Only present for AAC
+    pub aac_packet_type: Option<AACPacketType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioMessage {
+    pub header: AudioTagHeader,
+    /// Codec data following the tag header
+    pub data: Vec<u8>,
+}
+
+impl AudioMessage {
+    /// The message payload as sent over the wire, tag header included.
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload_vector = Vec::with_capacity(self.data.len() + 2);
+        MediaWriter::write_audio(self, &mut payload_vector);
+        payload_vector
+    }
+
+    pub fn is_sequence_header(&self) -> bool {
+        self.header.aac_packet_type == Some(AACPacketType::SequenceHeader)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    KeyFrame = 1,
+    InterFrame = 2,
+    DisposableInterFrame = 3,
+    GeneratedKeyFrame = 4,
+    VideoInfoCommand = 5,
+}
+
+impl TryFrom<u8> for FrameType {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(FrameType::KeyFrame),
+            2 => Ok(FrameType::InterFrame),
+            3 => Ok(FrameType::DisposableInterFrame),
+            4 => Ok(FrameType::GeneratedKeyFrame),
+            5 => Ok(FrameType::VideoInfoCommand),
+            _ => Err("Invalid frame type"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodecId {
+    JPEG = 1,
+    SorensonH263 = 2,
+    ScreenVideo = 3,
+    VP6 = 4,
+    VP6Alpha = 5,
+    ScreenVideo2 = 6,
+    AVC = 7,
+    /// Not part of the FLV specification, but widely used for H.265
+    HEVC = 12,
+}
+
+impl TryFrom<u8> for VideoCodecId {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(VideoCodecId::JPEG),
+            2 => Ok(VideoCodecId::SorensonH263),
+            3 => Ok(VideoCodecId::ScreenVideo),
+            4 => Ok(VideoCodecId::VP6),
+            5 => Ok(VideoCodecId::VP6Alpha),
+            6 => Ok(VideoCodecId::ScreenVideo2),
+            7 => Ok(VideoCodecId::AVC),
+            12 => Ok(VideoCodecId::HEVC),
+            _ => Err("Invalid video codec id"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AVCPacketType {
+    /// AVCDecoderConfigurationRecord
+    SequenceHeader = 0,
+    NALU = 1,
+    EndOfSequence = 2,
+}
+
+impl TryFrom<u8> for AVCPacketType {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AVCPacketType::SequenceHeader),
+            1 => Ok(AVCPacketType::NALU),
+            2 => Ok(AVCPacketType::EndOfSequence),
+            _ => Err("Invalid AVC packet type"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoTagHeader {
+    pub frame_type: FrameType,
+    pub codec_id: VideoCodecId,
+    /// Only present for AVC and HEVC
+    pub avc_packet_type: Option<AVCPacketType>,
+    /// Presentation minus decoding time in milliseconds, only present for
+    /// AVC and HEVC
+    pub composition_time: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoMessage {
+    pub header: VideoTagHeader,
+    /// Codec data following the tag header
+    pub data: Vec<u8>,
+}
+
+impl VideoMessage {
+    /// The message payload as sent over the wire, tag header included.
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload_vector = Vec::with_capacity(self.data.len() + 5);
+        MediaWriter::write_video(self, &mut payload_vector);
+        payload_vector
+    }
+
+    pub fn is_keyframe(&self) -> bool {
+        self.header.frame_type == FrameType::KeyFrame
+    }
+
+    pub fn is_sequence_header(&self) -> bool {
+        self.header.avc_packet_type == Some(AVCPacketType::SequenceHeader)
+    }
+}
@@ -0,0 +1,89 @@
+use nom::combinator::rest;
+use nom::number::complete::{be_i24, be_u8};
+
+use crate::errors::Error;
+use crate::media::packets::{
+    AACPacketType, AVCPacketType, AudioMessage, AudioTagHeader, FrameType, SoundFormat, SoundRate,
+    SoundSize, SoundType, VideoCodecId, VideoMessage, VideoTagHeader,
+};
+use crate::utils::nom::RTMPResult;
+
+pub struct MediaReader {}
+
+fn invalid_data(e: &str) -> nom::Err<Error<'static>> {
+    nom::Err::Failure(Error::IoError(e.to_string(), std::io::ErrorKind::InvalidData))
+}
+
+impl MediaReader {
+    pub fn read_audio_tag_header(payload: &[u8]) -> RTMPResult<'_, AudioTagHeader> {
+        let (i, flags) = be_u8(payload)?;
+
+        let sound_format = SoundFormat::try_from(flags >> 4).map_err(invalid_data)?;
+        let sound_rate = SoundRate::from(flags >> 2);
+        let sound_size = if flags & 0b10 != 0 { SoundSize::Bits16 } else { SoundSize::Bits8 };
+        let sound_type = if flags & 0b1 != 0 { SoundType::Stereo } else { SoundType::Mono };
+
+        let (i, aac_packet_type) = if sound_format == SoundFormat::AAC {
+            let (i, aac_packet_type) = be_u8(i)?;
+            (i, Some(AACPacketType::try_from(aac_packet_type).map_err(invalid_data)?))
+        } else {
+            (i, None)
+        };
+
+        Ok((i, AudioTagHeader {
+            sound_format,
+            sound_rate,
+            sound_size,
+            sound_type,
+            aac_packet_type,
+        }))
+    }
+
+    pub fn read_audio(payload: &[u8]) -> RTMPResult<'_, AudioMessage> {
+        let (i, header) = MediaReader::read_audio_tag_header(payload)?;
+        let (i, data) = rest(i)?;
+
+        Ok((i, AudioMessage {
+            header,
+            data: data.to_vec(),
+        }))
+    }
+
+    pub fn read_video_tag_header(payload: &[u8]) -> RTMPResult<'_, VideoTagHeader> {
+        let (i, flags) = be_u8(payload)?;
+
+        let frame_type = FrameType::try_from(flags >> 4).map_err(invalid_data)?;
+        let codec_id = VideoCodecId::try_from(flags & 0x0F).map_err(invalid_data)?;
+
+        // video info commands carry a single command byte instead of codec data
+        let has_avc_header = matches!(codec_id, VideoCodecId::AVC | VideoCodecId::HEVC)
+            && frame_type != FrameType::VideoInfoCommand;
+
+        let (i, avc_packet_type, composition_time) = if has_avc_header {
+            let (i, avc_packet_type) = be_u8(i)?;
+            let (i, composition_time) = be_i24(i)?;
+            let avc_packet_type = AVCPacketType::try_from(avc_packet_type).map_err(invalid_data)?;
+
+            (i, Some(avc_packet_type), Some(composition_time))
+        } else {
+            (i, None, None)
+        };
+
+        Ok((i, VideoTagHeader {
+            frame_type,
+            codec_id,
+            avc_packet_type,
+            composition_time,
+        }))
+    }
+
+    pub fn read_video(payload: &[u8]) -> RTMPResult<'_, VideoMessage> {
+        let (i, header) = MediaReader::read_video_tag_header(payload)?;
+        let (i, data) = rest(i)?;
+
+        Ok((i, VideoMessage {
+            header,
+            data: data.to_vec(),
+        }))
+    }
+}
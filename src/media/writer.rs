@@ -0,0 +1,80 @@
+use crate::media::packets::{AudioMessage, AudioTagHeader, VideoMessage, VideoTagHeader};
+
+pub struct MediaWriter {}
+
+impl MediaWriter {
+    pub fn write_audio_tag_header(header: &AudioTagHeader, payload_vector: &mut Vec<u8>) {
+        payload_vector.push(
+            (header.sound_format as u8) << 4
+                | (header.sound_rate as u8) << 2
+                | (header.sound_size as u8) << 1
+                | header.sound_type as u8,
+        );
+
+        if let Some(aac_packet_type) = header.aac_packet_type {
+            payload_vector.push(aac_packet_type as u8);
+        }
+    }
+
+    pub fn write_audio(audio: &AudioMessage, payload_vector: &mut Vec<u8>) {
+        MediaWriter::write_audio_tag_header(&audio.header, payload_vector);
+        payload_vector.extend_from_slice(&audio.data);
+    }
+
+    pub fn write_video_tag_header(header: &VideoTagHeader, payload_vector: &mut Vec<u8>) {
+        payload_vector.push((header.frame_type as u8) << 4 | header.codec_id as u8);
+
+        if let Some(avc_packet_type) = header.avc_packet_type {
+            payload_vector.push(avc_packet_type as u8);
+
+            // signed 24 bit big endian
+            let composition_time = header.composition_time.unwrap_or(0);
+            payload_vector.extend_from_slice(&composition_time.to_be_bytes()[1..]);
+        }
+    }
+
+    pub fn write_video(video: &VideoMessage, payload_vector: &mut Vec<u8>) {
+        MediaWriter::write_video_tag_header(&video.header, payload_vector);
+        payload_vector.extend_from_slice(&video.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::media::packets::{AVCPacketType, FrameType, SoundFormat, VideoCodecId};
+    use crate::media::reader::MediaReader;
+
+    #[test]
+    fn test_aac_round_trip() {
+        let payload = [0xAF, 0x01, 0x21, 0x10];
+        let (_, audio) = MediaReader::read_audio(&payload).unwrap();
+
+        assert_eq!(audio.header.sound_format, SoundFormat::AAC);
+        assert!(!audio.is_sequence_header());
+        assert_eq!(audio.data, vec![0x21, 0x10]);
+        assert_eq!(audio.payload(), payload.to_vec());
+    }
+
+    #[test]
+    fn test_avc_round_trip() {
+        // keyframe, NALU, composition time of -2
+        let payload = [0x17, 0x01, 0xFF, 0xFF, 0xFE, 0x65, 0x88];
+        let (_, video) = MediaReader::read_video(&payload).unwrap();
+
+        assert_eq!(video.header.frame_type, FrameType::KeyFrame);
+        assert_eq!(video.header.codec_id, VideoCodecId::AVC);
+        assert_eq!(video.header.avc_packet_type, Some(AVCPacketType::NALU));
+        assert_eq!(video.header.composition_time, Some(-2));
+        assert_eq!(video.payload(), payload.to_vec());
+    }
+
+    #[test]
+    fn test_video_info_command() {
+        let payload = [0x57, 0x00];
+        let (_, video) = MediaReader::read_video(&payload).unwrap();
+
+        assert_eq!(video.header.frame_type, FrameType::VideoInfoCommand);
+        assert_eq!(video.header.avc_packet_type, None);
+        assert_eq!(video.data, vec![0x00]);
+    }
+}
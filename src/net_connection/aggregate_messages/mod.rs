@@ -0,0 +1,5 @@
+pub mod reader;
+pub mod writer;
+
+/// Size of the FLV style tag header in front of every sub-message.
+pub const SUB_MESSAGE_HEADER_SIZE: u32 = 11;
@@ -0,0 +1,71 @@
+use nom::bytes::complete::take;
+use nom::number::complete::{be_u24, be_u32, be_u8};
+
+use crate::errors::Error;
+use crate::net_connection::packets::RTMPMessage;
+use crate::utils::nom::RTMPResult;
+
+use super::SUB_MESSAGE_HEADER_SIZE;
+
+pub struct AggregateMessageReader {}
+
+/// A sub-message as found in the aggregate, with its own timestamp.
+struct SubMessage<'a> {
+    message_type_id: u8,
+    timestamp: u32,
+    data: &'a [u8],
+}
+
+impl AggregateMessageReader {
+    fn read_sub_message(payload: &[u8]) -> RTMPResult<'_, SubMessage<'_>> {
+        let (i, message_type_id) = be_u8(payload)?;
+        let (i, data_size) = be_u24(i)?;
+        let (i, timestamp) = be_u24(i)?;
+        let (i, timestamp_extended) = be_u8(i)?;
+        let (i, _stream_id) = be_u24(i)?;
+        let (i, data) = take(data_size as usize)(i)?;
+        let (i, back_pointer) = be_u32(i)?;
+
+        if back_pointer != data_size + SUB_MESSAGE_HEADER_SIZE {
+            return Err(nom::Err::Failure(Error::IoError(
+                format!("Invalid aggregate back pointer {}, expected {}", back_pointer, data_size + SUB_MESSAGE_HEADER_SIZE),
+                std::io::ErrorKind::InvalidData,
+            )));
+        }
+
+        Ok((i, SubMessage {
+            message_type_id,
+            timestamp: (timestamp_extended as u32) << 24 | timestamp,
+            data,
+        }))
+    }
+
+    /// Splits an aggregate into its sub-messages. Sub-message timestamps are
+    /// rebased so that the first one lands on the timestamp of the aggregate.
+    pub fn read(aggregate: &RTMPMessage) -> std::io::Result<Vec<RTMPMessage>> {
+        let mut i = aggregate.payload.as_slice();
+        let mut messages = Vec::new();
+        let mut first_timestamp: Option<u32> = None;
+
+        while !i.is_empty() {
+            let (j, sub_message) = AggregateMessageReader::read_sub_message(i)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse aggregate message: {:?}", e)))?;
+
+            let first_timestamp = *first_timestamp.get_or_insert(sub_message.timestamp);
+
+            messages.push(RTMPMessage {
+                timestamp: aggregate
+                    .timestamp
+                    .wrapping_add(sub_message.timestamp.wrapping_sub(first_timestamp)),
+                message_type_id: sub_message.message_type_id,
+                message_stream_id: aggregate.message_stream_id,
+                chunk_stream_id: aggregate.chunk_stream_id,
+                payload: sub_message.data.to_vec(),
+            });
+
+            i = j;
+        }
+
+        Ok(messages)
+    }
+}
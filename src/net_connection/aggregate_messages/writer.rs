@@ -0,0 +1,125 @@
+use crate::chunk::packets::MessageTypeId;
+use crate::net_connection::packets::RTMPMessage;
+
+use super::SUB_MESSAGE_HEADER_SIZE;
+
+pub struct AggregateMessageWriter {}
+
+impl AggregateMessageWriter {
+    fn write_sub_message(
+        message: &RTMPMessage,
+        payload_vector: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        match MessageTypeId::try_from(message.message_type_id) {
+            Ok(MessageTypeId::AudioData)
+            | Ok(MessageTypeId::VideoData)
+            | Ok(MessageTypeId::DataAMF0)
+            | Ok(MessageTypeId::DataAMF3) => {}
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Message type {:?} can't be aggregated", message.message_type_id),
+                ))
+            }
+        }
+
+        let data_size = message.payload.len() as u32;
+        if data_size > 0xFFFFFF {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Sub-message is too large for an aggregate",
+            ));
+        }
+
+        payload_vector.push(message.message_type_id);
+        payload_vector.extend_from_slice(&data_size.to_be_bytes()[1..]);
+        payload_vector.extend_from_slice(&message.timestamp.to_be_bytes()[1..]);
+        payload_vector.push((message.timestamp >> 24) as u8);
+        payload_vector.extend_from_slice(&message.message_stream_id.to_be_bytes()[1..]);
+        payload_vector.extend_from_slice(&message.payload);
+        payload_vector.extend_from_slice(&(data_size + SUB_MESSAGE_HEADER_SIZE).to_be_bytes());
+
+        Ok(())
+    }
+
+    /// Builds an aggregate out of a batch of audio, video and data messages.
+    /// The aggregate takes the timestamp and stream of the first message.
+    pub fn write(messages: &[RTMPMessage]) -> std::io::Result<RTMPMessage> {
+        let first = messages.first().ok_or(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Can't build an empty aggregate",
+        ))?;
+
+        let mut payload_vector: Vec<u8> = Vec::new();
+
+        for message in messages {
+            AggregateMessageWriter::write_sub_message(message, &mut payload_vector)?;
+        }
+
+        Ok(RTMPMessage {
+            timestamp: first.timestamp,
+            message_type_id: MessageTypeId::AggregateMessage as u8,
+            message_stream_id: first.message_stream_id,
+            chunk_stream_id: first.chunk_stream_id,
+            payload: payload_vector,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net_connection::aggregate_messages::reader::AggregateMessageReader;
+
+    fn message(message_type_id: MessageTypeId, timestamp: u32, payload: Vec<u8>) -> RTMPMessage {
+        RTMPMessage {
+            timestamp,
+            message_type_id: message_type_id as u8,
+            message_stream_id: 1,
+            chunk_stream_id: 7,
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let aggregate = AggregateMessageWriter::write(&[
+            message(MessageTypeId::VideoData, 1000, vec![0x17, 1, 2, 3]),
+            message(MessageTypeId::AudioData, 1020, vec![0xAF, 1]),
+        ])
+        .unwrap();
+
+        assert_eq!(aggregate.timestamp, 1000);
+
+        let mut rebased = aggregate;
+        rebased.timestamp = 5000;
+
+        let messages = AggregateMessageReader::read(&rebased).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].timestamp, 5000);
+        assert_eq!(messages[0].payload, vec![0x17, 1, 2, 3]);
+        assert_eq!(messages[1].timestamp, 5020);
+        assert_eq!(messages[1].message_stream_id, 1);
+    }
+
+    #[test]
+    fn test_invalid_back_pointer() {
+        let mut aggregate = AggregateMessageWriter::write(&[
+            message(MessageTypeId::AudioData, 0, vec![0xAF, 1]),
+        ])
+        .unwrap();
+
+        let length = aggregate.payload.len();
+        aggregate.payload[length - 1] = 0;
+
+        assert!(AggregateMessageReader::read(&aggregate).is_err());
+    }
+
+    #[test]
+    fn test_reject_commands() {
+        assert!(AggregateMessageWriter::write(&[
+            message(MessageTypeId::CommandAMF0, 0, vec![]),
+        ])
+        .is_err());
+    }
+}
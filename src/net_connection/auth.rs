@@ -0,0 +1,267 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Everything but the unreserved characters of RFC 3986 is escaped in query values.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Challenge/response schemes servers ask for when rejecting connect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMod {
+    /// Adobe Media Server and Wowza
+    Adobe,
+    /// Limelight
+    Llnw,
+}
+
+impl AuthMod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMod::Adobe => "adobe",
+            AuthMod::Llnw => "llnw",
+        }
+    }
+}
+
+/// Keeps track of the authentication rounds of one connect. The rejection
+/// description tells which step comes next, each step is a new connection
+/// with parameters appended to the app and the tcUrl.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectAuthentication {
+    username: Option<String>,
+    password: Option<String>,
+    auth_mod: Option<AuthMod>,
+    /// Query appended to the app and tcUrl of the next connect
+    params: Option<String>,
+    response_sent: bool,
+}
+
+/// Values following `?reason=needauth` in a rejection description.
+fn parse_description_params(description: &str) -> Option<Vec<(&str, &str)>> {
+    let start = description.find("?reason=needauth")?;
+
+    Some(
+        description[start + 1..]
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .collect(),
+    )
+}
+
+fn find_param<'a>(params: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+}
+
+/// Escapes a value put in the query of the app and tcUrl.
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_VALUE).to_string()
+}
+
+fn md5_hex(input: &str) -> String {
+    format!("{:x}", md5::compute(input))
+}
+
+impl ConnectAuthentication {
+    pub fn new(username: Option<String>, password: Option<String>) -> Self {
+        ConnectAuthentication {
+            username,
+            password,
+            ..ConnectAuthentication::default()
+        }
+    }
+
+    pub fn params(&self) -> Option<&str> {
+        self.params.as_deref()
+    }
+
+    /// Looks at the description of a rejected connect and prepares the next
+    /// attempt. Returns false when there is nothing left to try, the
+    /// rejection is then final.
+    pub fn process_rejection(&mut self, description: &str, app: &str) -> bool {
+        if description.contains("?reason=authfailed") || description.contains("?reason=nosuchuser") {
+            return false;
+        }
+
+        let (username, password) = match (&self.username, &self.password) {
+            (Some(username), Some(password)) => (username.clone(), password.clone()),
+            _ => return false,
+        };
+
+        // a response was already rejected, the credentials are wrong
+        if self.response_sent {
+            return false;
+        }
+
+        if description.contains("code=403 need auth") {
+            let auth_mod = if description.contains("authmod=adobe") {
+                AuthMod::Adobe
+            } else if description.contains("authmod=llnw") {
+                AuthMod::Llnw
+            } else {
+                return false;
+            };
+
+            // the first rejection only names the scheme, ask for a challenge
+            if self.auth_mod.is_some() {
+                return false;
+            }
+            self.auth_mod = Some(auth_mod);
+            self.params = Some(format!("?authmod={}&user={}", auth_mod.as_str(), encode(&username)));
+            return true;
+        }
+
+        let params = match parse_description_params(description) {
+            Some(params) => params,
+            None => return false,
+        };
+
+        let auth_mod = match self.auth_mod {
+            Some(auth_mod) => auth_mod,
+            None if find_param(&params, "nonce").is_some() => AuthMod::Llnw,
+            None => AuthMod::Adobe,
+        };
+
+        let client_challenge = format!("{:08x}", rand::random::<u32>());
+
+        self.params = match auth_mod {
+            AuthMod::Adobe => {
+                let salt = find_param(&params, "salt").unwrap_or_default();
+                let opaque = find_param(&params, "opaque");
+                let challenge = find_param(&params, "challenge");
+
+                Some(ConnectAuthentication::adobe_params(
+                    &username,
+                    &password,
+                    salt,
+                    opaque,
+                    challenge,
+                    &client_challenge,
+                ))
+            }
+            AuthMod::Llnw => {
+                let nonce = match find_param(&params, "nonce") {
+                    Some(nonce) => nonce,
+                    None => return false,
+                };
+
+                Some(ConnectAuthentication::llnw_params(&username, &password, nonce, app, &client_challenge))
+            }
+        };
+
+        self.auth_mod = Some(auth_mod);
+        self.response_sent = true;
+
+        true
+    }
+
+    /// base64(md5(base64(md5(user + salt + password)) + opaque or challenge
+    /// + our own challenge))
+    pub fn adobe_params(
+        username: &str,
+        password: &str,
+        salt: &str,
+        opaque: Option<&str>,
+        challenge: Option<&str>,
+        client_challenge: &str,
+    ) -> String {
+        let hash = STANDARD.encode(md5::compute(format!("{}{}{}", username, salt, password)).0);
+
+        let server_challenge = opaque.or(challenge).unwrap_or_default();
+        let response = STANDARD.encode(md5::compute(format!("{}{}{}", hash, server_challenge, client_challenge)).0);
+
+        let mut params = format!(
+            "?authmod=adobe&user={}&challenge={}&response={}",
+            encode(username),
+            client_challenge,
+            response
+        );
+
+        if let Some(opaque) = opaque {
+            params.push_str("&opaque=");
+            params.push_str(&encode(opaque));
+        }
+
+        params
+    }
+
+    /// HTTP digest style response, with the app as the request path.
+    pub fn llnw_params(username: &str, password: &str, nonce: &str, app: &str, client_nonce: &str) -> String {
+        let realm = "live";
+        let method = "publish";
+        let qop = "auth";
+        let nc = "00000001";
+
+        let ha1 = md5_hex(&format!("{}:{}:{}", username, realm, password));
+
+        let path = if app.contains('/') {
+            format!("/{}", app)
+        } else {
+            format!("/{}/_definst_", app)
+        };
+        let ha2 = md5_hex(&format!("{}:{}", method, path));
+
+        let response = md5_hex(&format!("{}:{}:{}:{}:{}:{}", ha1, nonce, nc, client_nonce, qop, ha2));
+
+        format!(
+            "?authmod=llnw&user={}&nonce={}&cnonce={}&nc={}&response={}",
+            encode(username),
+            encode(nonce),
+            client_nonce,
+            nc,
+            response
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adobe_rounds() {
+        let mut auth = ConnectAuthentication::new(Some(String::from("user")), Some(String::from("pass")));
+
+        assert!(auth.process_rejection("[ AccessManager.Reject ] : [ code=403 need auth; authmod=adobe ] : ", "live"));
+        assert_eq!(auth.params(), Some("?authmod=adobe&user=user"));
+
+        assert!(auth.process_rejection(
+            "[ AccessManager.Reject ] : [ authmod=adobe ] : ?reason=needauth&user=user&salt=s4lt&challenge=ch4l&opaque=0p4q",
+            "live",
+        ));
+        let params = auth.params().unwrap();
+        assert!(params.starts_with("?authmod=adobe&user=user&challenge="));
+        assert!(params.ends_with("&opaque=0p4q"));
+
+        assert!(!auth.process_rejection("[ AccessManager.Reject ] : [ authmod=adobe ] : ?reason=authfailed", "live"));
+    }
+
+    #[test]
+    fn test_adobe_response() {
+        let params = ConnectAuthentication::adobe_params("user", "pass", "salt", None, Some("challenge"), "00000000");
+
+        let hash = STANDARD.encode(md5::compute("usersaltpass").0);
+        let response = STANDARD.encode(md5::compute(format!("{}challenge00000000", hash)).0);
+        assert_eq!(params, format!("?authmod=adobe&user=user&challenge=00000000&response={}", response));
+    }
+
+    #[test]
+    fn test_without_credentials() {
+        let mut auth = ConnectAuthentication::new(None, None);
+        assert!(!auth.process_rejection("[ code=403 need auth; authmod=llnw ]", "live"));
+    }
+
+    #[test]
+    fn test_escaped_params() {
+        let mut auth = ConnectAuthentication::new(Some(String::from("jane doe&co=1?")), Some(String::from("pass")));
+
+        assert!(auth.process_rejection("[ code=403 need auth; authmod=adobe ]", "live"));
+        assert_eq!(auth.params(), Some("?authmod=adobe&user=jane%20doe%26co%3D1%3F"));
+
+        let params = ConnectAuthentication::adobe_params("a&b", "pass", "salt", Some("op aque"), None, "00000000");
+        assert!(params.starts_with("?authmod=adobe&user=a%26b&"));
+        assert!(params.ends_with("&opaque=op%20aque"));
+
+        let params = ConnectAuthentication::llnw_params("a=b", "pass", "n?nce", "live", "00000000");
+        assert!(params.starts_with("?authmod=llnw&user=a%3Db&nonce=n%3Fnce&cnonce=00000000&"));
+    }
+}
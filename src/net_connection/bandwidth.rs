@@ -0,0 +1,325 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use flash_lso::amf0::write::write_value;
+use flash_lso::types::Value;
+
+use crate::net_connection::transaction_manager::{Responder, TransactionResult};
+use crate::net_connection::NetConnection;
+use crate::transport::Transport;
+
+/// Numbers in every payload of a burst, 1200 like the FMS sample application
+pub const DEFAULT_PAYLOAD_LENGTH: usize = 1200;
+
+/// Estimated bandwidth from the server to the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bandwidth {
+    pub kbps: f64,
+    pub latency: Duration,
+}
+
+impl Bandwidth {
+    /// Result of the server side check, `onBWDone(kbitDown, deltaDown, deltaTime, latency)`.
+    pub fn from_arguments(arguments: &[Value]) -> Option<Self> {
+        let number = |index: usize| match arguments.get(index) {
+            Some(Value::Number(number)) => Some(*number),
+            _ => None,
+        };
+
+        Some(Bandwidth {
+            kbps: number(0)?,
+            latency: Duration::from_millis(number(3).unwrap_or(0.0).max(0.0) as u64),
+        })
+    }
+
+    pub fn to_arguments(&self, delta_down: f64, delta_time: Duration) -> Vec<Value> {
+        vec![
+            Value::Number(self.kbps),
+            Value::Number(delta_down),
+            Value::Number(delta_time.as_millis() as f64),
+            Value::Number(self.latency.as_millis() as f64),
+        ]
+    }
+}
+
+/// Bytes an AMF0 payload takes on the wire.
+pub(crate) fn payload_size(arguments: &[Value]) -> usize {
+    let mut payload = Vec::new();
+    for argument in arguments {
+        let _ = write_value(&mut payload, &Rc::new(argument.clone()));
+    }
+
+    payload.len()
+}
+
+/// Client side of the check, answering the `onBWCheck` bursts of the server.
+#[derive(Debug, Default)]
+pub struct BandwidthCheck {
+    /// Bursts received so far, sent back as the answer of each
+    pub count: u32,
+    /// Whether we already asked the server to start a check
+    pub requested: bool,
+    first_check: Option<Instant>,
+    last_check: Option<Instant>,
+    /// Shortest time between two bursts, the server waits for every answer
+    shortest_gap: Option<Duration>,
+    bytes: usize,
+    /// Last result, reported by the server or measured by us
+    pub result: Option<Bandwidth>,
+}
+
+impl BandwidthCheck {
+    pub fn new() -> Self {
+        BandwidthCheck::default()
+    }
+
+    /// Records a burst and returns the value to answer it with.
+    pub fn on_check(&mut self, arguments: &[Value], now: Instant) -> u32 {
+        if let Some(last_check) = self.last_check {
+            let gap = now.duration_since(last_check);
+            self.shortest_gap = Some(self.shortest_gap.map_or(gap, |shortest_gap| shortest_gap.min(gap)));
+            self.bytes += payload_size(arguments);
+        }
+
+        self.first_check.get_or_insert(now);
+        self.last_check = Some(now);
+        self.count += 1;
+
+        self.count
+    }
+
+    /// Estimate from the bursts received, the first one only starts the clock.
+    pub fn measured(&self) -> Option<Bandwidth> {
+        let elapsed = self.last_check?.duration_since(self.first_check?);
+        let latency = self.shortest_gap?;
+
+        // every burst waited a round trip for the answer to the previous one
+        let transfer_time = elapsed
+            .checked_sub(latency * (self.count - 1))
+            .filter(|transfer_time| !transfer_time.is_zero())
+            .unwrap_or(elapsed);
+
+        if transfer_time.is_zero() {
+            return None;
+        }
+
+        Some(Bandwidth {
+            kbps: self.bytes as f64 * 8.0 / 1000.0 / transfer_time.as_secs_f64(),
+            latency,
+        })
+    }
+
+    /// Ends the check, preferring what the server measured.
+    pub fn on_done(&mut self, arguments: &[Value]) -> Option<Bandwidth> {
+        let result = Bandwidth::from_arguments(arguments).or_else(|| self.measured());
+        if result.is_some() {
+            self.result = result;
+        }
+        self.clear_bursts();
+
+        result
+    }
+
+    /// Forgets a check in progress, keeping the last result.
+    pub fn reset(&mut self) {
+        self.clear_bursts();
+        self.requested = false;
+    }
+
+    fn clear_bursts(&mut self) {
+        self.first_check = None;
+        self.last_check = None;
+        self.shortest_gap = None;
+        self.bytes = 0;
+        self.count = 0;
+    }
+}
+
+/// Server side of the check: sends `onBWCheck` bursts of growing size to the
+/// client over an established connection, then tells it the result with
+/// `onBWDone`.
+#[derive(Debug, Clone)]
+pub struct BandwidthProber {
+    pub payload_length: usize,
+    /// Bursts sent at most, after the one measuring latency
+    pub max_bursts: u32,
+    /// Payload growth between two bursts
+    pub multiplier: usize,
+    /// Stops sending bursts once the check took this long
+    pub max_duration: Duration,
+}
+
+impl Default for BandwidthProber {
+    fn default() -> Self {
+        BandwidthProber {
+            payload_length: DEFAULT_PAYLOAD_LENGTH,
+            max_bursts: 6,
+            multiplier: 3,
+            max_duration: Duration::from_secs(2),
+        }
+    }
+}
+
+impl BandwidthProber {
+    pub fn new() -> Self {
+        BandwidthProber::default()
+    }
+
+    pub fn payload_length(mut self, payload_length: usize) -> Self {
+        self.payload_length = payload_length;
+        self
+    }
+
+    pub fn max_bursts(mut self, max_bursts: u32) -> Self {
+        self.max_bursts = max_bursts;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: usize) -> Self {
+        self.multiplier = multiplier.max(1);
+        self
+    }
+
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    /// Runs the check and returns what was measured, also sent to the client.
+    pub fn probe<T: Transport>(&self, connection: &mut NetConnection<T>) -> std::io::Result<Bandwidth> {
+        let latency = self.burst(connection, Vec::new())?;
+
+        let started = Instant::now();
+        let mut bytes = 0;
+        let mut payload_length = self.payload_length;
+        let mut bursts = 0;
+
+        while bursts < self.max_bursts {
+            let payload: Vec<Value> = (0..payload_length)
+                .map(|index| Value::Number((index as f64 * 1.618).fract()))
+                .collect();
+            let arguments = vec![Value::StrictArray(payload.into_iter().map(Rc::new).collect())];
+
+            bytes += payload_size(&arguments);
+            self.burst(connection, arguments)?;
+            bursts += 1;
+
+            if started.elapsed() >= self.max_duration {
+                break;
+            }
+
+            payload_length *= self.multiplier;
+        }
+
+        let delta_time = started.elapsed();
+        let transfer_time = delta_time.saturating_sub(latency * bursts).max(Duration::from_millis(1));
+
+        let bandwidth = Bandwidth {
+            kbps: bytes as f64 * 8.0 / 1000.0 / transfer_time.as_secs_f64(),
+            latency,
+        };
+
+        let delta_down = bytes as f64 * 8.0 / 1000.0;
+        connection.call("onBWDone", None, bandwidth.to_arguments(delta_down, delta_time), None)?;
+
+        Ok(bandwidth)
+    }
+
+    /// Sends one burst and waits for its answer, returning the round trip time.
+    /// A failed call, rejected, timed out or lost with the connection, ends
+    /// the check.
+    fn burst<T: Transport>(&self, connection: &mut NetConnection<T>, arguments: Vec<Value>) -> std::io::Result<Duration> {
+        let answer: Rc<RefCell<Option<TransactionResult>>> = Rc::new(RefCell::new(None));
+        let result_answer = answer.clone();
+        let error_answer = answer.clone();
+
+        let sent = Instant::now();
+        connection.call(
+            "onBWCheck",
+            None,
+            arguments,
+            Some(Responder::with_error(
+                move |_, _| *result_answer.borrow_mut() = Some(TransactionResult::Result),
+                move |_, _| *error_answer.borrow_mut() = Some(TransactionResult::Error),
+            )),
+        )?;
+
+        loop {
+            let result = *answer.borrow();
+            match result {
+                Some(TransactionResult::Result) => return Ok(sent.elapsed()),
+                Some(TransactionResult::Error) => return Err(std::io::Error::other("onBWCheck failed")),
+                None => {}
+            }
+
+            if let Err(error) = connection.process_message() {
+                // the read also fails when the call timed out, the answer tells
+                if answer.borrow().is_none() {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::allocate_net_connection_context;
+    use crate::net_connection::packets::{AMFCommandMessage, RTMPMessageType};
+    use crate::net_connection::reader::RTMPReader;
+    use crate::net_connection::state::ConnectionState;
+    use crate::net_connection::writer::RTMPWriter;
+    use crate::transport::memory_transport::MemoryTransport;
+
+    #[test]
+    fn test_check() {
+        let mut check = BandwidthCheck::new();
+        let start = Instant::now();
+        let payload = vec![Value::StrictArray((0..1000).map(|_| Rc::new(Value::Number(0.0))).collect())];
+
+        check.on_check(&[], start);
+        check.on_check(&payload, start + Duration::from_millis(50));
+        assert_eq!(check.on_check(&payload, start + Duration::from_millis(150)), 3);
+
+        // 2 * 9005 bytes over 150ms minus two 50ms round trips
+        let bandwidth = check.measured().unwrap();
+        assert_eq!(bandwidth.latency, Duration::from_millis(50));
+        assert!((bandwidth.kbps - 2881.6).abs() < 0.1);
+
+        let reported = check.on_done(&[Value::Number(5000.0), Value::Number(0.0), Value::Number(0.0), Value::Number(20.0)]);
+        assert_eq!(reported.unwrap().kbps, 5000.0);
+        assert_eq!(check.result.unwrap().latency, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_probe_rejected() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+
+        let server = std::thread::spawn(move || {
+            let mut server = allocate_net_connection_context(server);
+
+            let transaction_id = match RTMPReader::read(&mut server).unwrap() {
+                (_, RTMPMessageType::AMF0Command(command)) => command.transaction_id,
+                (_, message) => panic!("unexpected message {:?}", message),
+            };
+
+            let answer = AMFCommandMessage {
+                procedure_name: "_error".to_string(),
+                transaction_id,
+                command_object: None,
+                optional_arguments: vec![Value::Null],
+            };
+            RTMPWriter::write(RTMPMessageType::AMF0Command(answer), &mut server).unwrap();
+
+            // stays up, a probe still waiting would hang instead of failing
+            server.transport
+        });
+
+        assert!(BandwidthProber::new().probe(&mut connection).is_err());
+        drop(server.join().unwrap());
+    }
+}
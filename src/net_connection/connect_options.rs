@@ -0,0 +1,297 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use flash_lso::types::{Element, Value};
+
+use crate::context::{ConnectionArgs, ObjectEncoding};
+use crate::net_connection::endpoints::{fallback_tc_urls, ConnectStrategy};
+use crate::utils::url::TcUrl;
+
+pub const DEFAULT_FLASH_VER: &str = "WIN 32,0,0,465";
+pub const DEFAULT_CAPABILITIES: u32 = 239;
+/// Every audio codec Flash Player supports
+pub const DEFAULT_AUDIO_CODECS: u32 = 3575;
+/// Every video codec Flash Player supports
+pub const DEFAULT_VIDEO_CODECS: u32 = 252;
+/// Seeking to frames other than keyframes
+pub const DEFAULT_VIDEO_FUNCTION: u32 = 1;
+
+pub const DEFAULT_MAX_REDIRECTS: u32 = 5;
+
+/// Everything sent in the connect command, built up with chained setters.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// Overrides the app taken from the tcUrl
+    pub app: Option<String>,
+    pub flash_ver: String,
+    pub swf_url: String,
+    pub page_url: String,
+    pub fpad: bool,
+    pub capabilities: u32,
+    pub audio_codecs: u32,
+    pub video_codecs: u32,
+    pub video_function: u32,
+    pub object_encoding: ObjectEncoding,
+    /// Arguments sent after the command object, often used for auth
+    pub additional_args: Vec<Value>,
+    /// Properties added to the command object
+    pub extra_properties: Vec<Element>,
+    /// Credentials for servers asking for adobe or llnw authentication,
+    /// taken from the tcUrl userinfo when not set
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Shared secret of a Wowza application with SecureToken enabled
+    pub secure_token: Option<String>,
+    /// Redirects followed before connect fails
+    pub max_redirects: u32,
+    /// Equivalent tcUrls tried after the one given to connect
+    pub endpoints: Vec<String>,
+    /// Also tries the ports Flash Player falls back to for every endpoint
+    pub port_fallback: bool,
+    /// Order the endpoints are tried in, `Parallel` only sorts them by reachability
+    pub connect_strategy: ConnectStrategy,
+    /// Time given to each endpoint to complete the handshake and connect
+    pub attempt_timeout: Option<Duration>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            app: None,
+            flash_ver: DEFAULT_FLASH_VER.to_string(),
+            swf_url: String::new(),
+            page_url: String::new(),
+            fpad: false,
+            capabilities: DEFAULT_CAPABILITIES,
+            audio_codecs: DEFAULT_AUDIO_CODECS,
+            video_codecs: DEFAULT_VIDEO_CODECS,
+            video_function: DEFAULT_VIDEO_FUNCTION,
+            object_encoding: ObjectEncoding::AMF0,
+            additional_args: Vec::new(),
+            extra_properties: Vec::new(),
+            username: None,
+            password: None,
+            secure_token: None,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            endpoints: Vec::new(),
+            port_fallback: false,
+            connect_strategy: ConnectStrategy::Sequential,
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl ConnectOptions {
+    pub fn new() -> Self {
+        ConnectOptions::default()
+    }
+
+    pub fn app(mut self, app: &str) -> Self {
+        self.app = Some(app.to_string());
+        self
+    }
+
+    pub fn flash_ver(mut self, flash_ver: &str) -> Self {
+        self.flash_ver = flash_ver.to_string();
+        self
+    }
+
+    pub fn swf_url(mut self, swf_url: &str) -> Self {
+        self.swf_url = swf_url.to_string();
+        self
+    }
+
+    pub fn page_url(mut self, page_url: &str) -> Self {
+        self.page_url = page_url.to_string();
+        self
+    }
+
+    /// Whether a proxy is used
+    pub fn fpad(mut self, fpad: bool) -> Self {
+        self.fpad = fpad;
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: u32) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn audio_codecs(mut self, audio_codecs: u32) -> Self {
+        self.audio_codecs = audio_codecs;
+        self
+    }
+
+    pub fn video_codecs(mut self, video_codecs: u32) -> Self {
+        self.video_codecs = video_codecs;
+        self
+    }
+
+    pub fn video_function(mut self, video_function: u32) -> Self {
+        self.video_function = video_function;
+        self
+    }
+
+    /// Encoding to ask for, commands switch to AMF3 only if the server agrees.
+    pub fn object_encoding(mut self, object_encoding: ObjectEncoding) -> Self {
+        self.object_encoding = object_encoding;
+        self
+    }
+
+    pub fn argument(mut self, argument: Value) -> Self {
+        self.additional_args.push(argument);
+        self
+    }
+
+    pub fn arguments(mut self, arguments: Vec<Value>) -> Self {
+        self.additional_args.extend(arguments);
+        self
+    }
+
+    /// Adds a property to the command object, replacing a standard one of the
+    /// same name.
+    pub fn property(mut self, name: &str, value: Value) -> Self {
+        self.extra_properties.retain(|element| element.name != name);
+        self.extra_properties.push(Element {
+            name: name.to_string(),
+            value: Rc::new(value),
+        });
+        self
+    }
+
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.username = Some(username.to_string());
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Answers the secureToken challenge of the connect result with this secret.
+    pub fn secure_token(mut self, shared_secret: &str) -> Self {
+        self.secure_token = Some(shared_secret.to_string());
+        self
+    }
+
+    pub fn max_redirects(mut self, max_redirects: u32) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn endpoint(mut self, tc_url: &str) -> Self {
+        self.endpoints.push(tc_url.to_string());
+        self
+    }
+
+    pub fn endpoints(mut self, tc_urls: &[&str]) -> Self {
+        self.endpoints.extend(tc_urls.iter().map(|tc_url| tc_url.to_string()));
+        self
+    }
+
+    pub fn port_fallback(mut self, port_fallback: bool) -> Self {
+        self.port_fallback = port_fallback;
+        self
+    }
+
+    pub fn connect_strategy(mut self, connect_strategy: ConnectStrategy) -> Self {
+        self.connect_strategy = connect_strategy;
+        self
+    }
+
+    pub fn attempt_timeout(mut self, attempt_timeout: Duration) -> Self {
+        self.attempt_timeout = Some(attempt_timeout);
+        self
+    }
+
+    /// Every tcUrl to try, in order, starting with the one given to connect.
+    pub fn candidates(&self, tc_url: &str) -> std::io::Result<Vec<String>> {
+        let mut candidates = Vec::new();
+
+        for candidate in std::iter::once(tc_url).chain(self.endpoints.iter().map(String::as_str)) {
+            let expanded = if self.port_fallback {
+                fallback_tc_urls(candidate)?
+            } else {
+                vec![candidate.to_string()]
+            };
+
+            for candidate in expanded {
+                if !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    pub fn to_connection_args(&self, tc_url: &TcUrl) -> ConnectionArgs {
+        ConnectionArgs {
+            app: self.app.clone().unwrap_or_else(|| tc_url.app.clone()),
+            flash_ver: self.flash_ver.clone(),
+            swf_url: self.swf_url.clone(),
+            tc_url: tc_url.full_url.clone(),
+            fpad: self.fpad,
+            capabilities: self.capabilities,
+            audio_codecs: self.audio_codecs,
+            video_codecs: self.video_codecs,
+            video_function: self.video_function,
+            page_url: self.page_url.clone(),
+            object_encoding: self.object_encoding,
+            additional_args: self.additional_args.clone(),
+            extra_properties: self.extra_properties.clone(),
+        }
+    }
+}
+
+impl ConnectionArgs {
+    /// The connect command object.
+    pub fn to_command_object(&self) -> Value {
+        let mut elements = vec![
+            ("app", Value::String(self.app.clone())),
+            ("flashVer", Value::String(self.flash_ver.clone())),
+            ("swfUrl", Value::String(self.swf_url.clone())),
+            ("tcUrl", Value::String(self.tc_url.clone())),
+            ("fpad", Value::Bool(self.fpad)),
+            ("capabilities", Value::Number(self.capabilities as f64)),
+            ("audioCodecs", Value::Number(self.audio_codecs as f64)),
+            ("videoCodecs", Value::Number(self.video_codecs as f64)),
+            ("videoFunction", Value::Number(self.video_function as f64)),
+            ("pageUrl", Value::String(self.page_url.clone())),
+            ("objectEncoding", Value::Number(self.object_encoding as i32 as f64)),
+        ]
+        .into_iter()
+        .filter(|(name, _)| !self.extra_properties.iter().any(|element| element.name == *name))
+        .map(|(name, value)| Element {
+            name: name.to_string(),
+            value: Rc::new(value),
+        })
+        .collect::<Vec<_>>();
+
+        elements.extend(self.extra_properties.iter().cloned());
+
+        Value::Object(elements, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::amf::{get_number, get_string};
+    use crate::utils::url::parse_tc_url;
+
+    #[test]
+    fn test_command_object() {
+        let tc_url = parse_tc_url("rtmp://localhost/live").unwrap();
+        let connection_args = ConnectOptions::new()
+            .page_url("https://example.com/player")
+            .property("capabilities", Value::Number(15.0))
+            .property("token", Value::String(String::from("secret")))
+            .argument(Value::String(String::from("user")))
+            .to_connection_args(&tc_url);
+
+        let command_object = connection_args.to_command_object();
+        assert_eq!(get_string(&command_object, "app"), Some("live"));
+        assert_eq!(get_string(&command_object, "pageUrl"), Some("https://example.com/player"));
+        assert_eq!(get_number(&command_object, "capabilities"), Some(15.0));
+        assert_eq!(get_string(&command_object, "token"), Some("secret"));
+        assert_eq!(connection_args.additional_args.len(), 1);
+    }
+}
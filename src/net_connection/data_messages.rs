@@ -0,0 +1,328 @@
+use flash_lso::types::{Element, Value};
+use std::rc::Rc;
+
+use crate::context::ObjectEncoding;
+use crate::net_connection::packets::DataMessage;
+use crate::utils::amf::{get_number, get_properties, get_property, get_string};
+
+pub const ON_META_DATA: &str = "onMetaData";
+pub const SET_DATA_FRAME: &str = "@setDataFrame";
+pub const CLEAR_DATA_FRAME: &str = "@clearDataFrame";
+pub const RTMP_SAMPLE_ACCESS: &str = "|RtmpSampleAccess";
+pub const ON_TEXT_DATA: &str = "onTextData";
+pub const ON_CUE_POINT: &str = "onCuePoint";
+
+/// Stream metadata as sent in onMetaData. Well known properties get their own
+/// field, anything else is kept in `extra`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetaData {
+    pub duration: Option<f64>,
+    pub file_size: Option<f64>,
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    pub video_codec_id: Option<f64>,
+    pub video_data_rate: Option<f64>,
+    pub frame_rate: Option<f64>,
+    pub audio_codec_id: Option<f64>,
+    pub audio_data_rate: Option<f64>,
+    pub audio_sample_rate: Option<f64>,
+    pub audio_sample_size: Option<f64>,
+    pub stereo: Option<bool>,
+    pub encoder: Option<String>,
+    pub extra: Vec<Element>,
+}
+
+impl MetaData {
+    pub fn from_value(value: &Value) -> Self {
+        let mut meta_data = MetaData::default();
+
+        for element in get_properties(value) {
+            let number = match element.value.as_ref() {
+                Value::Number(n) => Some(*n),
+                Value::Integer(n) => Some(*n as f64),
+                _ => None,
+            };
+
+            match (element.name.as_str(), element.value.as_ref(), number) {
+                ("duration", _, Some(number)) => meta_data.duration = Some(number),
+                ("filesize", _, Some(number)) => meta_data.file_size = Some(number),
+                ("width", _, Some(number)) => meta_data.width = Some(number),
+                ("height", _, Some(number)) => meta_data.height = Some(number),
+                ("videocodecid", _, Some(number)) => meta_data.video_codec_id = Some(number),
+                ("videodatarate", _, Some(number)) => meta_data.video_data_rate = Some(number),
+                ("framerate", _, Some(number)) => meta_data.frame_rate = Some(number),
+                ("audiocodecid", _, Some(number)) => meta_data.audio_codec_id = Some(number),
+                ("audiodatarate", _, Some(number)) => meta_data.audio_data_rate = Some(number),
+                ("audiosamplerate", _, Some(number)) => meta_data.audio_sample_rate = Some(number),
+                ("audiosamplesize", _, Some(number)) => meta_data.audio_sample_size = Some(number),
+                ("stereo", Value::Bool(stereo), _) => meta_data.stereo = Some(*stereo),
+                ("encoder", Value::String(encoder), _) => meta_data.encoder = Some(encoder.clone()),
+                _ => meta_data.extra.push(element.clone()),
+            }
+        }
+
+        meta_data
+    }
+
+    /// Encodes the metadata as the ECMA array encoders put in onMetaData.
+    pub fn to_value(&self) -> Value {
+        let numbers = [
+            ("duration", self.duration),
+            ("filesize", self.file_size),
+            ("width", self.width),
+            ("height", self.height),
+            ("videocodecid", self.video_codec_id),
+            ("videodatarate", self.video_data_rate),
+            ("framerate", self.frame_rate),
+            ("audiocodecid", self.audio_codec_id),
+            ("audiodatarate", self.audio_data_rate),
+            ("audiosamplerate", self.audio_sample_rate),
+            ("audiosamplesize", self.audio_sample_size),
+        ];
+
+        let mut elements: Vec<Element> = numbers
+            .iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| Element {
+                    name: name.to_string(),
+                    value: Rc::new(Value::Number(value)),
+                })
+            })
+            .collect();
+
+        if let Some(stereo) = self.stereo {
+            elements.push(Element {
+                name: String::from("stereo"),
+                value: Rc::new(Value::Bool(stereo)),
+            });
+        }
+
+        if let Some(encoder) = &self.encoder {
+            elements.push(Element {
+                name: String::from("encoder"),
+                value: Rc::new(Value::String(encoder.clone())),
+            });
+        }
+
+        elements.extend(self.extra.iter().cloned());
+
+        let length = elements.len() as u32;
+        Value::ECMAArray(Vec::new(), elements, length)
+    }
+}
+
+/// Timed text, as carried by onTextData.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextData {
+    pub text: String,
+    pub language: Option<String>,
+    pub track_id: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CuePointType {
+    Event,
+    Navigation,
+}
+
+/// A cue point embedded in the stream, as carried by onCuePoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuePoint {
+    pub name: String,
+    /// Position in seconds
+    pub time: f64,
+    pub cue_point_type: CuePointType,
+    pub parameters: Vec<Element>,
+}
+
+impl DataMessage {
+    pub fn new(handler: &str, arguments: Vec<Value>) -> Self {
+        DataMessage {
+            handler: handler.to_string(),
+            arguments,
+            object_encoding: ObjectEncoding::AMF0,
+        }
+    }
+
+    pub fn on_meta_data(meta_data: &MetaData) -> Self {
+        DataMessage::new(ON_META_DATA, vec![meta_data.to_value()])
+    }
+
+    /// Metadata sent by a publisher, the server stores it and hands it out as
+    /// onMetaData to everyone playing the stream.
+    pub fn set_data_frame(meta_data: &MetaData) -> Self {
+        DataMessage::new(
+            SET_DATA_FRAME,
+            vec![Value::String(ON_META_DATA.to_string()), meta_data.to_value()],
+        )
+    }
+
+    pub fn clear_data_frame() -> Self {
+        DataMessage::new(CLEAR_DATA_FRAME, vec![Value::String(ON_META_DATA.to_string())])
+    }
+
+    /// Tells the player whether it may access the raw audio and video data.
+    pub fn rtmp_sample_access(audio: bool, video: bool) -> Self {
+        DataMessage::new(RTMP_SAMPLE_ACCESS, vec![Value::Bool(audio), Value::Bool(video)])
+    }
+
+    pub fn on_text_data(text_data: &TextData) -> Self {
+        let mut elements = vec![Element {
+            name: String::from("text"),
+            value: Rc::new(Value::String(text_data.text.clone())),
+        }];
+
+        if let Some(language) = &text_data.language {
+            elements.push(Element {
+                name: String::from("language"),
+                value: Rc::new(Value::String(language.clone())),
+            });
+        }
+
+        if let Some(track_id) = text_data.track_id {
+            elements.push(Element {
+                name: String::from("trackid"),
+                value: Rc::new(Value::Number(track_id)),
+            });
+        }
+
+        DataMessage::new(ON_TEXT_DATA, vec![Value::Object(elements, None)])
+    }
+
+    pub fn on_cue_point(cue_point: &CuePoint) -> Self {
+        let cue_point_type = match cue_point.cue_point_type {
+            CuePointType::Event => "event",
+            CuePointType::Navigation => "navigation",
+        };
+
+        DataMessage::new(
+            ON_CUE_POINT,
+            vec![Value::Object(
+                vec![
+                    Element {
+                        name: String::from("name"),
+                        value: Rc::new(Value::String(cue_point.name.clone())),
+                    },
+                    Element {
+                        name: String::from("time"),
+                        value: Rc::new(Value::Number(cue_point.time)),
+                    },
+                    Element {
+                        name: String::from("type"),
+                        value: Rc::new(Value::String(cue_point_type.to_string())),
+                    },
+                    Element {
+                        name: String::from("parameters"),
+                        value: Rc::new(Value::Object(cue_point.parameters.clone(), None)),
+                    },
+                ],
+                None,
+            )],
+        )
+    }
+
+    /// Metadata of either an onMetaData or an @setDataFrame message.
+    pub fn meta_data(&self) -> Option<MetaData> {
+        let value = match self.handler.as_str() {
+            ON_META_DATA => self.arguments.first()?,
+            SET_DATA_FRAME => match self.arguments.as_slice() {
+                [Value::String(name), value, ..] if name == ON_META_DATA => value,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(MetaData::from_value(value))
+    }
+
+    /// Audio and video access granted by |RtmpSampleAccess.
+    pub fn sample_access(&self) -> Option<(bool, bool)> {
+        if self.handler != RTMP_SAMPLE_ACCESS {
+            return None;
+        }
+
+        match self.arguments.as_slice() {
+            [Value::Bool(audio), Value::Bool(video), ..] => Some((*audio, *video)),
+            _ => None,
+        }
+    }
+
+    pub fn text_data(&self) -> Option<TextData> {
+        if self.handler != ON_TEXT_DATA {
+            return None;
+        }
+
+        let value = self.arguments.first()?;
+
+        Some(TextData {
+            text: get_string(value, "text")?.to_string(),
+            language: get_string(value, "language").map(str::to_string),
+            track_id: get_number(value, "trackid"),
+        })
+    }
+
+    pub fn cue_point(&self) -> Option<CuePoint> {
+        if self.handler != ON_CUE_POINT {
+            return None;
+        }
+
+        let value = self.arguments.first()?;
+
+        let cue_point_type = match get_string(value, "type") {
+            Some("navigation") => CuePointType::Navigation,
+            _ => CuePointType::Event,
+        };
+
+        Some(CuePoint {
+            name: get_string(value, "name")?.to_string(),
+            time: get_number(value, "time")?,
+            cue_point_type,
+            parameters: get_property(value, "parameters")
+                .map(|parameters| get_properties(parameters).to_vec())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_data_frame_meta_data() {
+        let meta_data = MetaData {
+            duration: Some(12.5),
+            width: Some(1280.0),
+            stereo: Some(true),
+            extra: vec![Element {
+                name: String::from("custom"),
+                value: Rc::new(Value::String(String::from("value"))),
+            }],
+            ..MetaData::default()
+        };
+
+        let message = DataMessage::set_data_frame(&meta_data);
+        assert_eq!(message.handler, SET_DATA_FRAME);
+        assert_eq!(message.meta_data(), Some(meta_data));
+    }
+
+    #[test]
+    fn test_cue_point() {
+        let cue_point = CuePoint {
+            name: String::from("chapter"),
+            time: 4.0,
+            cue_point_type: CuePointType::Navigation,
+            parameters: vec![],
+        };
+
+        let message = DataMessage::on_cue_point(&cue_point);
+        assert_eq!(message.cue_point(), Some(cue_point));
+        assert_eq!(message.meta_data(), None);
+    }
+
+    #[test]
+    fn test_sample_access() {
+        let message = DataMessage::rtmp_sample_access(true, false);
+        assert_eq!(message.sample_access(), Some((true, false)));
+    }
+}
@@ -0,0 +1,113 @@
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use crate::utils::url::parse_tc_url;
+
+/// Port Flash Player falls back to when 1935 is blocked
+pub const FALLBACK_PORT: u16 = 443;
+
+/// How candidate endpoints are tried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectStrategy {
+    /// One after the other, in the given order
+    #[default]
+    Sequential,
+    /// Probes every candidate with a plain TCP connect at once, then goes
+    /// through them one after the other, those answering first first. Only
+    /// the order changes, handshake and connect still happen one at a time.
+    Parallel,
+}
+
+/// Candidates Flash Player would go through for a tcUrl: rtmp on 1935, then
+/// on 443. A tcUrl with an explicit port is only tried as is.
+///
+/// Flash went on with rtmpt on port 80, which is left out since tunneling
+/// isn't supported.
+pub fn fallback_tc_urls(tc_url: &str) -> std::io::Result<Vec<String>> {
+    let mut url = Url::parse(tc_url).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    if url.scheme() != "rtmp" || url.port().is_some() {
+        return Ok(vec![tc_url.to_string()]);
+    }
+
+    let _ = url.set_port(Some(FALLBACK_PORT));
+
+    Ok(vec![tc_url.to_string(), url.to_string()])
+}
+
+/// Opens a TCP connection to every candidate at once and orders them by how
+/// fast they answered. Candidates that couldn't be reached in time come last,
+/// in their original order.
+///
+/// The probe connections are closed right away, the server sees them as
+/// clients leaving before the handshake. Probes still under way when the
+/// timeout ends keep running on their detached threads until their own
+/// connect times out, their answer is ignored.
+pub(crate) fn order_by_reachability(candidates: &[String], timeout: Duration) -> Vec<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    for (index, candidate) in candidates.iter().enumerate() {
+        let tc_url = match parse_tc_url(candidate) {
+            Ok(tc_url) => tc_url,
+            Err(_) => continue,
+        };
+
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let addrs = match (tc_url.host.as_str(), tc_url.port).to_socket_addrs() {
+                Ok(addrs) => addrs,
+                Err(_) => return,
+            };
+
+            for addr in addrs {
+                if TcpStream::connect_timeout(&addr, timeout).is_ok() {
+                    let _ = sender.send(index);
+                    return;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let deadline = Instant::now() + timeout;
+    let mut reachable = Vec::new();
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(index) => reachable.push(index),
+            Err(_) => break,
+        }
+    }
+
+    let mut ordered: Vec<String> = reachable.iter().map(|&index| candidates[index].clone()).collect();
+    ordered.extend(
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !reachable.contains(index))
+            .map(|(_, candidate)| candidate.clone()),
+    );
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_tc_urls() {
+        assert_eq!(
+            fallback_tc_urls("rtmp://localhost/live").unwrap(),
+            vec!["rtmp://localhost/live", "rtmp://localhost:443/live"]
+        );
+        assert_eq!(
+            fallback_tc_urls("rtmp://localhost:1936/live").unwrap(),
+            vec!["rtmp://localhost:1936/live"]
+        );
+    }
+}
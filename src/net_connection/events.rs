@@ -0,0 +1,62 @@
+use flash_lso::types::Value;
+
+use crate::media::packets::{AudioMessage, VideoMessage};
+use crate::net_connection::packets::DataMessage;
+use crate::net_connection::state::ConnectionState;
+use crate::net_connection::status::NetStatus;
+use crate::shared_object::SharedObjectEvent;
+
+/// Events kept for `poll_event`, and by every stream for `take_events`, at
+/// most. A caller that never takes them only holds on to the last ones.
+pub const MAX_QUEUED_EVENTS: usize = 4096;
+
+/// What happened on a `NetConnection`, returned one at a time by `poll_event`.
+#[derive(Clone, Debug)]
+pub enum NetConnectionEvent {
+    /// The server accepted connect, on this tcUrl after redirects and fallbacks
+    Connected { tc_url: String },
+    /// onStatus of the connection, on stream 0, or of one of its streams
+    Status { stream_id: u32, status: NetStatus },
+    /// A call of the server, after its handler ran
+    Command {
+        stream_id: u32,
+        name: String,
+        command_object: Value,
+        arguments: Vec<Value>,
+    },
+    Data { stream_id: u32, timestamp: u32, data: DataMessage },
+    Audio { stream_id: u32, timestamp: u32, audio: AudioMessage },
+    Video { stream_id: u32, timestamp: u32, video: VideoMessage },
+    /// A message of a type we don't handle, or audio and video we couldn't
+    /// parse, as received. `send_raw` passes it on.
+    Raw {
+        type_id: u8,
+        stream_id: u32,
+        timestamp: u32,
+        payload: Vec<u8>,
+    },
+    /// Changes the server made to a shared object, already applied to its data
+    SharedObjectSync { name: String, events: Vec<SharedObjectEvent> },
+    /// The server changed the size of the chunks it sends
+    ChunkSizeChanged { size: u32 },
+    /// The server pinged us, the answer has already been sent
+    Ping { timestamp: u32 },
+    /// Every move between lifecycle states
+    StateChanged { from: ConnectionState, to: ConnectionState },
+    /// A connection that was up is gone, closed by us, the server or the network
+    Closed,
+}
+
+impl NetConnectionEvent {
+    /// Audio, video, data and raw messages, the first to go when the queue
+    /// is full.
+    pub fn is_media(&self) -> bool {
+        matches!(
+            self,
+            NetConnectionEvent::Data { .. }
+                | NetConnectionEvent::Audio { .. }
+                | NetConnectionEvent::Video { .. }
+                | NetConnectionEvent::Raw { .. }
+        )
+    }
+}
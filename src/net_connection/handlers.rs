@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use flash_lso::types::Value;
+
+use crate::net_connection::status::{NetStatus, NetStatusCode};
+
+/// What a handler answers to a server call. Only sent back when the call
+/// carries a transaction id.
+#[derive(Clone, Debug)]
+pub enum CallResponse {
+    /// Nothing to send back
+    None,
+    /// Replied with `_result`
+    Result(Value),
+    /// Replied with `_error`, usually an info object with code and description
+    Error(Value),
+}
+
+pub type Handler = Box<dyn Fn(Value, &[Value]) -> CallResponse>;
+
+/// Named handlers for the commands the server calls on the client, like the
+/// `client` object of an AS3 NetConnection.
+pub struct HandlerRegistry {
+    handlers: HashMap<String, Handler>,
+    default_handler: Option<Handler>,
+}
+
+impl std::fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HandlerRegistry")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("default_handler", &self.default_handler.is_some())
+            .finish()
+    }
+}
+
+impl Default for HandlerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        HandlerRegistry {
+            handlers: HashMap::new(),
+            default_handler: None,
+        }
+    }
+
+    pub fn register(&mut self, name: &str, handler: Handler) {
+        self.handlers.insert(name.to_string(), handler);
+    }
+
+    pub fn unregister(&mut self, name: &str) {
+        self.handlers.remove(name);
+    }
+
+    pub fn has_handler(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    /// Handler for every call without a registered handler, it gets the
+    /// procedure name as a string in front of the arguments.
+    pub fn set_default_handler(&mut self, handler: Handler) {
+        self.default_handler = Some(handler);
+    }
+
+    pub fn call(&self, name: &str, command_object: Value, arguments: &[Value]) -> CallResponse {
+        if let Some(handler) = self.handlers.get(name) {
+            return handler(command_object, arguments);
+        }
+
+        match &self.default_handler {
+            Some(default_handler) => {
+                let mut default_arguments = vec![Value::String(name.to_string())];
+                default_arguments.extend_from_slice(arguments);
+
+                default_handler(command_object, &default_arguments)
+            }
+            None => CallResponse::Error(
+                NetStatus::error(NetStatusCode::CallFailed, &format!("Method not found ({})", name)).to_value(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_handler() {
+        let mut registry = HandlerRegistry::new();
+        registry.register("add", Box::new(|_, arguments: &[Value]| match arguments {
+            [Value::Number(a), Value::Number(b)] => CallResponse::Result(Value::Number(a + b)),
+            _ => CallResponse::None,
+        }));
+
+        match registry.call("add", Value::Null, &[Value::Number(1.0), Value::Number(2.0)]) {
+            CallResponse::Result(Value::Number(sum)) => assert_eq!(sum, 3.0),
+            response => panic!("unexpected response {:?}", response),
+        }
+
+        assert!(matches!(registry.call("unknown", Value::Null, &[]), CallResponse::Error(_)));
+
+        registry.set_default_handler(Box::new(|_, arguments: &[Value]| match arguments.first() {
+            Some(Value::String(name)) if name == "unknown" => CallResponse::None,
+            _ => CallResponse::Error(Value::Null),
+        }));
+        assert!(matches!(registry.call("unknown", Value::Null, &[]), CallResponse::None));
+    }
+}
@@ -1,3 +1,14 @@
+pub mod aggregate_messages;
+pub mod auth;
+pub mod bandwidth;
+pub mod connect_options;
+pub mod data_messages;
+pub mod endpoints;
+pub mod events;
+pub mod handlers;
+pub mod reconnect;
+pub mod state;
+pub mod status;
 pub mod transaction_manager;
 pub mod user_control_messages;
 
@@ -5,23 +16,52 @@ pub mod packets;
 pub mod reader;
 pub mod writer;
 
+use crate::chunk::limits::ChunkLimits;
+use crate::chunk::scheduler::MessagePriority;
 use crate::context::{
-    allocate_net_connection_context, ConnectionArgs, NetConnectionContext, ObjectEncoding,
+    allocate_net_connection_context, NetConnectionContext, ObjectEncoding,
+    MAX_CHUNK_SIZE,
 };
+use crate::errors::ProtocolError;
 use crate::handshake::RTMPHandshake;
-use crate::net_connection::packets::{AMFCommandMessage, RTMPMessageType};
-use crate::net_connection::transaction_manager::TransactionResult;
+use crate::net_connection::auth::ConnectAuthentication;
+use crate::net_connection::bandwidth::Bandwidth;
+use crate::net_connection::connect_options::ConnectOptions;
+use crate::net_connection::endpoints::{order_by_reachability, ConnectStrategy};
+use crate::net_connection::events::{NetConnectionEvent, MAX_QUEUED_EVENTS};
+use crate::net_connection::handlers::CallResponse;
+use crate::net_connection::packets::{AMFCommandMessage, RTMPMessage, RTMPMessageHeader, RTMPMessageType};
+use crate::net_connection::reconnect::ReconnectPolicy;
+use crate::net_connection::state::ConnectionState;
+use crate::net_connection::status::{NetStatus, NetStatusCode};
+use crate::net_connection::transaction_manager::{Responder, TransactionResult};
 use crate::shared_object::SharedObject;
 use crate::transport::Transport;
-use crate::utils::url::parse_tc_url;
+use crate::net_stream::{NetStream, NetStreamEvent};
+use crate::utils::amf::{get_number, get_string};
+use crate::utils::tea::decrypt_token;
+use crate::utils::url::{parse_tc_url, TcUrl};
 
-use flash_lso::types::{Element, Value};
+use flash_lso::types::Value;
 use packets::{SetChunkSize, SetPeerBandwidth, UserControlMessage, WindowAcknowledgementSize};
 use reader::RTMPReader;
+use std::cell::RefCell;
+use std::io::ErrorKind;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use writer::RTMPWriter;
 
+/// Time given to endpoints to accept a TCP connection when racing them.
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answer of the server to a connect command.
+struct ConnectOutcome {
+    result: TransactionResult,
+    command_object: Value,
+    arguments: Vec<Value>,
+}
+
 pub struct NetConnection<T: Transport> {
     pub(crate) context: NetConnectionContext<T>,
 }
@@ -37,121 +77,379 @@ impl<T: Transport> NetConnection<T> {
         &mut self.context
     }
 
-    fn send_connect_request<F: Fn(Value, &[Value]) + 'static>(
-        &mut self,
-        callback: F,
-    ) -> std::io::Result<()>
-    where
-        F: Fn(Value, &[Value]) -> (),
-    {
+    fn send_connect_request(&mut self, responder: Responder) -> std::io::Result<()> {
         let connection_args = self.context.connection_args.as_ref().unwrap();
 
-        let transaction_id = self
-            .context
-            .transaction_manager
-            .initialize_transaction(Box::new(callback));
+        let transaction_id = self.context.transaction_manager.initialize_transaction(responder)?;
 
         let command = RTMPMessageType::AMF0Command(AMFCommandMessage {
             procedure_name: "connect".to_string(),
-            transaction_id: transaction_id,
-            command_object: Some(Value::Object(
-                vec![
-                    Element {
-                        name: String::from("videoCodecs"),
-                        value: Rc::new(Value::Number(connection_args.video_codecs.clone() as f64)),
-                    },
-                    Element {
-                        name: String::from("audioCodecs"),
-                        value: Rc::new(Value::Number(connection_args.audio_codecs.clone() as f64)),
-                    },
-                    Element {
-                        name: String::from("flashVer"),
-                        value: Rc::new(Value::String(connection_args.flash_ver.clone())),
-                    },
-                    Element {
-                        name: String::from("app"),
-                        value: Rc::new(Value::String(connection_args.app.clone())),
-                    },
-                    Element {
-                        name: String::from("tcUrl"),
-                        value: Rc::new(Value::String(connection_args.tc_url.clone())),
-                    },
-                    Element {
-                        name: String::from("videoFunction"),
-                        value: Rc::new(
-                            Value::Number(connection_args.video_function.clone() as f64),
-                        ),
-                    },
-                    Element {
-                        name: String::from("capabilities"),
-                        value: Rc::new(Value::Number(239.0)),
-                    },
-                    Element {
-                        name: String::from("pageUrl"),
-                        value: Rc::new(Value::String(connection_args.page_url.clone())),
-                    },
-                    Element {
-                        name: String::from("fpad"),
-                        value: Rc::new(Value::Bool(connection_args.fpad.clone())),
-                    },
-                    Element {
-                        name: String::from("swfUrl"),
-                        value: Rc::new(Value::String(connection_args.swf_url.clone())),
-                    },
-                    Element {
-                        name: String::from("objectEncoding"),
-                        value: Rc::new(Value::Number(
-                            connection_args.object_encoding.clone() as i32 as f64,
-                        )),
-                    },
-                ],
-                None,
-            )),
+            transaction_id,
+            command_object: Some(connection_args.to_command_object()),
             optional_arguments: connection_args.additional_args.clone(),
         });
 
+        self.context.connect_transaction_id = Some(transaction_id);
+        self.context.object_encoding = ObjectEncoding::AMF0;
+
+        // connect always goes out as AMF0, it is what negotiates the encoding
         RTMPWriter::write(command, &mut self.context)?;
 
         Ok(())
     }
 
-    pub fn connect<F: Fn(Value, &[Value]) + 'static>(
+    /// Sets the object encoding requested by `connect`, `connect_with` takes
+    /// it from its options instead. Commands are only sent as AMF3 once the
+    /// server agrees to it in its connect result.
+    pub fn set_object_encoding(&mut self, object_encoding: ObjectEncoding) {
+        self.context.requested_object_encoding = object_encoding;
+    }
+
+    pub fn object_encoding(&self) -> ObjectEncoding {
+        self.context.object_encoding
+    }
+
+    /// tcUrl of the established connection, the last one when redirected.
+    pub fn tc_url(&self) -> Option<&str> {
+        self.context.tc_url.as_deref()
+    }
+
+    /// Sends a command in the object encoding negotiated during connect.
+    pub fn send_command(&mut self, command: AMFCommandMessage, header: RTMPMessageHeader) -> std::io::Result<()> {
+        let message = match self.context.object_encoding {
+            ObjectEncoding::AMF0 => RTMPMessageType::AMF0Command(command),
+            ObjectEncoding::AMF3 => RTMPMessageType::AMF3Command(command),
+        };
+
+        RTMPWriter::write_with_header(message, header, &mut self.context)
+            .map_err(|error| self.process_io_error(error))
+    }
+
+    /// Calls a method on the server, like AS3's `NetConnection.call`. Without a
+    /// responder the call goes out with transaction id 0 and no answer is
+    /// expected. Returns the transaction id, which can be used to cancel it.
+    pub fn call(
         &mut self,
-        tc_url: &str,
-        callback: F,
-    ) -> std::io::Result<()>
+        procedure_name: &str,
+        command_object: Option<Value>,
+        arguments: Vec<Value>,
+        responder: Option<Responder>,
+    ) -> std::io::Result<u32> {
+        self.call_on_stream(0, procedure_name, command_object, arguments, responder)
+    }
+
+    /// Same as `call`, sent on a given message stream.
+    pub fn call_on_stream(
+        &mut self,
+        stream_id: u32,
+        procedure_name: &str,
+        command_object: Option<Value>,
+        arguments: Vec<Value>,
+        responder: Option<Responder>,
+    ) -> std::io::Result<u32> {
+        let transaction_id = match responder {
+            Some(responder) => self.context.transaction_manager.initialize_transaction(responder)?,
+            None => 0,
+        };
+
+        let result = self.send_command(
+            AMFCommandMessage {
+                procedure_name: procedure_name.to_string(),
+                transaction_id,
+                command_object,
+                optional_arguments: arguments,
+            },
+            RTMPMessageHeader {
+                timestamp: 0,
+                message_stream_id: stream_id,
+            },
+        );
+
+        // a call that never went out won't be answered
+        if result.is_err() && transaction_id != 0 {
+            self.context.transaction_manager.cancel_transaction(transaction_id);
+        }
+
+        result.map(|_| transaction_id)
+    }
+
+    /// Connects with the default options and waits for the answer. The callback
+    /// gets it whether connect succeeded or not, like netStatus in AS3.
+    pub fn connect<F>(&mut self, tc_url: &str, callback: F) -> std::io::Result<()>
     where
-        F: Fn(Value, &[Value]) -> (),
+        F: Fn(Value, &[Value]) + 'static,
     {
-        let tc_url = parse_tc_url(tc_url)?;
+        let callback = Rc::new(callback);
+        let error_callback = callback.clone();
 
-        assert_eq!(
-            tc_url.protocol, "rtmp",
-            "Currently only base RTMP flavor is supported"
+        let options = ConnectOptions::new().object_encoding(self.context.requested_object_encoding);
+        let responder = Responder::with_error(
+            move |properties, information: &[Value]| callback(properties, information),
+            move |properties, information: &[Value]| error_callback(properties, information),
         );
 
-        let connection_args = ConnectionArgs {
-            app: tc_url.app,
-            flash_ver: "WIN 32,0,0,465".to_string(),
-            swf_url: "".to_string(),
-            tc_url: tc_url.full_url,
-            fpad: false,
-            audio_codecs: 3575,
-            video_codecs: 252,
-            video_function: 1,
-            page_url: "".to_string(),
-            object_encoding: ObjectEncoding::AMF0,
-            additional_args: vec![],
+        self.connect_with(tc_url, options, responder)
+    }
+
+    /// Connects with every field of the connect command taken from `options`,
+    /// blocking until the server answered. Servers asking for adobe or llnw
+    /// authentication are answered with the configured credentials, only the
+    /// final answer reaches the responder.
+    pub fn connect_with(&mut self, tc_url: &str, options: ConnectOptions, responder: Responder) -> std::io::Result<()> {
+        self.context.connect_request = Some((tc_url.to_string(), options.clone()));
+
+        let outcome = self.connect_to_endpoints(tc_url, &options)?;
+        responder.respond(outcome.result, outcome.command_object, &outcome.arguments);
+
+        Ok(())
+    }
+
+    /// Goes through the candidate endpoints until one completes the handshake
+    /// and answers connect. A rejection is final, other candidates are only
+    /// tried when an endpoint can't be reached or times out.
+    fn connect_to_endpoints(&mut self, tc_url: &str, options: &ConnectOptions) -> std::io::Result<ConnectOutcome> {
+        let mut candidates = options.candidates(tc_url)?;
+
+        if options.connect_strategy == ConnectStrategy::Parallel && candidates.len() > 1 {
+            let timeout = options.attempt_timeout.unwrap_or(DEFAULT_PROBE_TIMEOUT);
+            candidates = order_by_reachability(&candidates, timeout);
+        }
+
+        let mut last_error = None;
+        // the attempt timeout only bounds connecting, the caller's applies after
+        let timeout = self.context.transport.timeout();
+
+        for candidate in candidates {
+            if options.attempt_timeout.is_some() {
+                self.context.transport.set_timeout(options.attempt_timeout)?;
+            }
+            self.context.connect_deadline = options.attempt_timeout.map(|timeout| Instant::now() + timeout);
+
+            let result = self.establish_connection(&candidate, options);
+
+            self.context.connect_deadline = None;
+            self.context.transport.set_timeout(timeout)?;
+
+            match result {
+                Ok(outcome) => {
+                    if outcome.result == TransactionResult::Result {
+                        self.set_state(ConnectionState::Connected)?;
+
+                        let tc_url = self.context.tc_url.clone().unwrap_or(candidate);
+                        self.dispatch_event(NetConnectionEvent::Connected { tc_url });
+                    } else {
+                        // the server closes the connection after a rejection
+                        self.close_transport();
+                    }
+
+                    return Ok(outcome);
+                }
+                Err(error) => {
+                    self.close_transport();
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(std::io::Error::new(ErrorKind::InvalidInput, "No endpoint to connect to")))
+    }
+
+    /// Connects, following redirects of load balancing edges, and returns the
+    /// final answer to connect.
+    fn establish_connection(&mut self, tc_url: &str, options: &ConnectOptions) -> std::io::Result<ConnectOutcome> {
+        let first_tc_url = parse_tc_url(tc_url)?;
+
+        // credentials of the first tcUrl still apply after a redirect
+        let username = options.username.clone().or(first_tc_url.username.clone());
+        let password = options.password.clone().or(first_tc_url.password.clone());
+
+        let mut tc_url = first_tc_url;
+        let mut visited = vec![tc_url.full_url.clone()];
+
+        loop {
+            let authentication = ConnectAuthentication::new(username.clone(), password.clone());
+            let outcome = self.authenticate_connection(&tc_url, options, authentication)?;
+
+            let redirect = match NetConnection::<T>::get_redirect(&outcome) {
+                Some(redirect) => redirect,
+                None => {
+                    if outcome.result == TransactionResult::Result {
+                        self.context.tc_url = Some(tc_url.full_url);
+                    }
+
+                    return Ok(outcome);
+                }
+            };
+
+            let redirect_tc_url = NetConnection::<T>::check_redirect(&visited, &redirect, options.max_redirects)?;
+
+            let _ = self.context.transport.disconnect();
+
+            visited.push(redirect_tc_url.full_url.clone());
+            tc_url = redirect_tc_url;
+        }
+    }
+
+    /// Parses the target of a redirect, refusing it past `max_redirects`, when
+    /// it was already visited or isn't rtmp.
+    fn check_redirect(visited: &[String], redirect: &str, max_redirects: u32) -> std::io::Result<TcUrl> {
+        if visited.len() > max_redirects as usize {
+            return Err(ProtocolError::TooManyRedirects { limit: max_redirects }.into());
+        }
+
+        let redirect_tc_url = parse_tc_url(redirect)?;
+        NetConnection::<T>::check_protocol(&redirect_tc_url)?;
+
+        if visited.contains(&redirect_tc_url.full_url) {
+            return Err(ProtocolError::RedirectLoop(redirect_tc_url.full_url).into());
+        }
+
+        Ok(redirect_tc_url)
+    }
+
+    /// Only plain rtmp can be spoken, tunneled and encrypted flavors can't.
+    fn check_protocol(tc_url: &TcUrl) -> std::io::Result<()> {
+        if tc_url.protocol != "rtmp" {
+            return Err(ProtocolError::UnsupportedProtocol(tc_url.protocol.clone()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Target of a rejection carrying `ex.code` 302 and `ex.redirect`.
+    fn get_redirect(outcome: &ConnectOutcome) -> Option<String> {
+        if outcome.result != TransactionResult::Error {
+            return None;
+        }
+
+        let status = NetStatus::from_arguments(&outcome.arguments)?;
+        if status.code != NetStatusCode::ConnectRejected {
+            return None;
+        }
+
+        let ex = status.get_extra("ex")?;
+        if get_number(ex, "code") != Some(302.0) {
+            return None;
+        }
+
+        get_string(ex, "redirect").map(str::to_string)
+    }
+
+    /// Connects to one tcUrl, going through authentication rounds and
+    /// answering the secure token challenge.
+    fn authenticate_connection(
+        &mut self,
+        tc_url: &TcUrl,
+        options: &ConnectOptions,
+        mut authentication: ConnectAuthentication,
+    ) -> std::io::Result<ConnectOutcome> {
+        NetConnection::<T>::check_protocol(tc_url)?;
+
+        loop {
+            let outcome = self.connect_once(tc_url, options, authentication.params())?;
+
+            if outcome.result == TransactionResult::Error {
+                let description = NetStatus::from_arguments(&outcome.arguments)
+                    .and_then(|status| status.description)
+                    .unwrap_or_default();
+                let app = options.app.clone().unwrap_or(tc_url.app.clone());
+
+                if authentication.process_rejection(&description, &app) {
+                    // the server closes the connection after a rejection anyway
+                    let _ = self.context.transport.disconnect();
+                    continue;
+                }
+            }
+
+            if outcome.result == TransactionResult::Result {
+                if let Some(shared_secret) = &options.secure_token {
+                    self.send_secure_token_response(shared_secret, &outcome.arguments)?;
+                }
+            }
+
+            return Ok(outcome);
+        }
+    }
+
+    /// Wowza drops the connection unless the secureToken challenge of the
+    /// connect result comes back decrypted with the shared secret. A challenge
+    /// that doesn't decrypt to text means the secret is wrong.
+    fn send_secure_token_response(&mut self, shared_secret: &str, arguments: &[Value]) -> std::io::Result<()> {
+        let challenge = match arguments.iter().find_map(|argument| get_string(argument, "secureToken")) {
+            Some(challenge) => challenge,
+            None => return Ok(()),
         };
 
+        let response = String::from_utf8(decrypt_token(shared_secret, challenge)).map_err(|_| {
+            std::io::Error::new(ErrorKind::InvalidData, "secureToken challenge didn't decrypt to text")
+        })?;
+
+        self.call("secureTokenResponse", None, vec![Value::String(response)], None)?;
+
+        Ok(())
+    }
+
+    /// Opens the transport, does the handshake and sends connect, then
+    /// processes messages until its answer comes in.
+    fn connect_once(
+        &mut self,
+        tc_url: &TcUrl,
+        options: &ConnectOptions,
+        auth_params: Option<&str>,
+    ) -> std::io::Result<ConnectOutcome> {
+        let mut connection_args = options.to_connection_args(tc_url);
+        if let Some(auth_params) = auth_params {
+            connection_args.app.push_str(auth_params);
+            connection_args.tc_url.push_str(auth_params);
+        }
+
+        self.context.reset_session();
+        self.context.requested_object_encoding = options.object_encoding;
         self.context.connection_args = Some(connection_args);
 
-        self.context.transport.connect(tc_url.host, tc_url.port)?;
+        self.set_state(ConnectionState::Handshaking)?;
+        self.context.transport.connect(tc_url.host.clone(), tc_url.port)?;
         RTMPHandshake::new().do_handshake(&mut self.context)?;
+        self.set_state(ConnectionState::Connecting)?;
 
-        self.send_connect_request(callback)?;
+        let outcome: Rc<RefCell<Option<ConnectOutcome>>> = Rc::new(RefCell::new(None));
+        let result_outcome = outcome.clone();
+        let error_outcome = outcome.clone();
 
-        Ok(())
+        self.send_connect_request(Responder::with_error(
+            move |command_object, arguments: &[Value]| {
+                *result_outcome.borrow_mut() = Some(ConnectOutcome {
+                    result: TransactionResult::Result,
+                    command_object,
+                    arguments: arguments.to_vec(),
+                });
+            },
+            move |command_object, arguments: &[Value]| {
+                *error_outcome.borrow_mut() = Some(ConnectOutcome {
+                    result: TransactionResult::Error,
+                    command_object,
+                    arguments: arguments.to_vec(),
+                });
+            },
+        ))?;
+
+        loop {
+            let answer = outcome.borrow_mut().take();
+            if let Some(answer) = answer {
+                return Ok(answer);
+            }
+
+            if self.context.connect_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(std::io::Error::new(ErrorKind::TimedOut, "Connect timed out"));
+            }
+
+            // a drop here fails this connect instead of reconnecting
+            match self.process_message() {
+                // an expired call, maybe connect itself, or the attempt deadline
+                Err(error) if error.kind() == ErrorKind::TimedOut => continue,
+                result => result?,
+            };
+        }
     }
 
     pub(crate) fn send_shared_object(&mut self, name: String) -> std::io::Result<()> {
@@ -169,34 +467,247 @@ impl<T: Transport> NetConnection<T> {
         self.context.window_ack_size = Some(window_ack_size.size);
     }
 
-    fn process_set_chunk_size(&mut self, set_chunk_size: SetChunkSize) {
-        self.context.chunk_size = set_chunk_size.size;
+    /// Announces a new chunk size for the messages we send. The writer only
+    /// switches to the new size once the SetChunkSize message itself has been
+    /// sent using the previous one.
+    pub fn set_chunk_size(&mut self, size: u32) -> std::io::Result<()> {
+        if size == 0 || size > MAX_CHUNK_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid chunk size {}", size),
+            ));
+        }
+
+        RTMPWriter::write(
+            RTMPMessageType::SetChunkSize(SetChunkSize { size }),
+            &mut self.context,
+        )
+
+    }
+
+    pub fn in_chunk_size(&self) -> u32 {
+        self.context.in_chunk_size
+    }
+
+    pub fn out_chunk_size(&self) -> u32 {
+        self.context.out_chunk_size
+    }
+
+    /// Queues a message without sending it, for `flush` and `flush_chunks`
+    /// to send. Commands and control messages sent in the meantime go out
+    /// ahead of queued audio and video, even halfway through a keyframe.
+    pub fn queue_message(&mut self, message: RTMPMessageType, header: RTMPMessageHeader) -> std::io::Result<()> {
+        RTMPWriter::enqueue_with_header(message, header, &mut self.context)
+    }
+
+    /// Sends every queued message, control messages and commands going ahead
+    /// of audio and video.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        RTMPWriter::flush(&mut self.context)
+    }
+
+    /// Sends at most `max_chunks` queued chunks, returns whether the queue is
+    /// empty now. Sending media this way keeps large messages from holding
+    /// back whatever is sent in between.
+    pub fn flush_chunks(&mut self, max_chunks: usize) -> std::io::Result<bool> {
+        RTMPWriter::flush_chunks(&mut self.context, MessagePriority::Video, max_chunks)?;
+
+        Ok(self.context.outgoing.is_empty())
+    }
+
+    /// Sends a batch of audio, video and data messages as a single aggregate
+    /// message, which saves the chunk headers of every sub-message.
+    pub fn send_aggregate(&mut self, messages: &[RTMPMessage]) -> std::io::Result<()> {
+        RTMPWriter::write_aggregate(messages, &mut self.context)
+    }
+
+    /// Replaces the limits applied to incoming chunks.
+    pub fn set_chunk_limits(&mut self, chunk_limits: ChunkLimits) {
+        self.context.chunk_limits = chunk_limits;
+    }
+
+    fn process_set_chunk_size(&mut self, set_chunk_size: SetChunkSize) -> std::io::Result<()> {
+        if set_chunk_size.size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Peer announced a chunk size of 0",
+            ));
+        }
+
+        if set_chunk_size.size > self.context.chunk_limits.max_chunk_size {
+            return Err(ProtocolError::ChunkSizeTooLarge {
+                size: set_chunk_size.size,
+                limit: self.context.chunk_limits.max_chunk_size,
+            }.into());
+        }
+
+        // only affects how we read the peer's chunks, our own stay untouched
+        self.context.in_chunk_size = set_chunk_size.size;
+        self.dispatch_event(NetConnectionEvent::ChunkSizeChanged { size: set_chunk_size.size });
+
+        Ok(())
     }
 
     fn process_set_peer_bandwidth(&mut self, _peer_bandwidth: SetPeerBandwidth) {
         // println!("not how to implement");
     }
 
-    fn process_user_control_message(&mut self, user_control_message: UserControlMessage) {
-        // println!("not how to implement");
+    pub(crate) fn send_set_buffer_length(&mut self, stream_id: u32, buffer_length: u32) -> std::io::Result<()> {
+        RTMPWriter::write(
+            RTMPMessageType::UserControlMessage(UserControlMessage::SetBufferLength {
+                stream_id,
+                buffer_length,
+            }),
+            &mut self.context,
+        )
+    }
+
+    /// Queues an event for `poll_event`. Past `MAX_QUEUED_EVENTS` the oldest
+    /// media event makes room, then the oldest event of any kind.
+    pub(crate) fn dispatch_event(&mut self, event: NetConnectionEvent) {
+        let events = &mut self.context.events;
+
+        if events.len() >= MAX_QUEUED_EVENTS {
+            match events.iter().position(NetConnectionEvent::is_media) {
+                Some(index) => {
+                    events.remove(index);
+                }
+                None => {
+                    events.pop_front();
+                }
+            }
+        }
+
+        events.push_back(event);
+    }
+
+    fn dispatch_stream_event(&mut self, stream_id: u32, event: NetStreamEvent) {
+        if let Some(net_stream) = self.context.get_net_stream(stream_id) {
+            net_stream.lock().unwrap().dispatch_event(event);
+        }
+    }
+
+    fn process_user_control_message(&mut self, user_control_message: UserControlMessage) -> std::io::Result<()> {
         match user_control_message {
             UserControlMessage::PingRequest { timestamp } => {
                 let response = UserControlMessage::PingResponse { timestamp };
                 RTMPWriter::write(
                     RTMPMessageType::UserControlMessage(response),
                     &mut self.context,
-                )
-                .unwrap();
+                )?;
+
+                self.dispatch_event(NetConnectionEvent::Ping { timestamp });
+            }
+
+            UserControlMessage::StreamBegin { stream_id } => {
+                self.dispatch_stream_event(stream_id, NetStreamEvent::Begin)
+            }
+            UserControlMessage::StreamEOF { stream_id } => {
+                self.dispatch_stream_event(stream_id, NetStreamEvent::EndOfFile)
+            }
+            UserControlMessage::StreamDry { stream_id } => {
+                self.dispatch_stream_event(stream_id, NetStreamEvent::Dry)
             }
+            UserControlMessage::StreamIsRecorded { stream_id } => {
+                self.dispatch_stream_event(stream_id, NetStreamEvent::Recorded)
+            }
+
+            // only ever sent by clients
+            UserControlMessage::SetBufferLength { .. } | UserControlMessage::PingResponse { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    /// Switches to AMF3 commands when the server accepted objectEncoding 3.
+    fn negotiate_object_encoding(&mut self, command: &AMFCommandMessage) {
+        let accepted_encoding = command
+            .optional_arguments
+            .first()
+            .and_then(|information| get_number(information, "objectEncoding"));
+
+        if accepted_encoding == Some(ObjectEncoding::AMF3 as i32 as f64)
+            && self.context.requested_object_encoding == ObjectEncoding::AMF3
+        {
+            self.context.object_encoding = ObjectEncoding::AMF3;
+        }
+    }
 
-            _ => todo!(
-                "user control message not implemented, {:?}",
-                user_control_message
-            ),
+    fn process_stream_status(&mut self, stream_id: u32, command: AMFCommandMessage) {
+        if let Some(status) = NetStatus::from_arguments(&command.optional_arguments) {
+            self.dispatch_stream_event(stream_id, NetStreamEvent::Status(status.clone()));
+            self.dispatch_event(NetConnectionEvent::Status { stream_id, status });
         }
     }
 
-    fn process_amf0_command(&mut self, command: AMFCommandMessage) {
+    /// Registers a handler for a command the server calls on us. Its response
+    /// is sent back when the call carries a transaction id.
+    pub fn register_handler<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(Value, &[Value]) -> CallResponse + 'static,
+    {
+        self.context.handlers.register(name, Box::new(handler));
+    }
+
+    pub fn unregister_handler(&mut self, name: &str) {
+        self.context.handlers.unregister(name);
+    }
+
+    /// Handles calls without a registered handler. Without one, they are
+    /// answered with a NetConnection.Call.Failed error.
+    pub fn set_default_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(Value, &[Value]) -> CallResponse + 'static,
+    {
+        self.context.handlers.set_default_handler(Box::new(handler));
+    }
+
+    fn process_call(&mut self, command: AMFCommandMessage, header: RTMPMessageHeader) -> std::io::Result<()> {
+        let command_object = command.command_object.unwrap_or(Value::Null);
+        let response = self.context.handlers.call(
+            &command.procedure_name,
+            command_object.clone(),
+            &command.optional_arguments,
+        );
+
+        self.dispatch_event(NetConnectionEvent::Command {
+            stream_id: header.message_stream_id,
+            name: command.procedure_name,
+            command_object,
+            arguments: command.optional_arguments,
+        });
+
+        // calls with transaction id 0 don't expect an answer
+        let (procedure_name, value) = match response {
+            _ if command.transaction_id == 0 => return Ok(()),
+            CallResponse::None => return Ok(()),
+            CallResponse::Result(value) => ("_result", value),
+            CallResponse::Error(value) => ("_error", value),
+        };
+
+        self.send_command(
+            AMFCommandMessage {
+                procedure_name: procedure_name.to_string(),
+                transaction_id: command.transaction_id,
+                command_object: None,
+                optional_arguments: vec![value],
+            },
+            RTMPMessageHeader {
+                timestamp: 0,
+                message_stream_id: header.message_stream_id,
+            },
+        )
+    }
+
+    fn process_command(&mut self, command: AMFCommandMessage, header: RTMPMessageHeader) -> std::io::Result<()> {
+        if self.context.connect_transaction_id == Some(command.transaction_id) {
+            self.context.connect_transaction_id = None;
+
+            if command.procedure_name == "_result" {
+                self.negotiate_object_encoding(&command);
+            }
+        }
+
         if command.procedure_name == "_result" || command.procedure_name == "_error" {
             let result = if command.procedure_name == "_result" {
                 TransactionResult::Result
@@ -207,61 +718,1117 @@ impl<T: Transport> NetConnection<T> {
             return self
                 .context
                 .transaction_manager
-                .finalize_transaction(command.transaction_id, result, command)
-                .unwrap();
+                .finalize_transaction(command.transaction_id, result, command);
         }
 
-        todo!(
-            "server tried to call a client command, procedure name: {}",
-            command.procedure_name
-        );
+        if command.procedure_name == "onStatus" && header.message_stream_id == 0 {
+            if let Some(status) = NetStatus::from_arguments(&command.optional_arguments) {
+                if status.code == NetStatusCode::ConnectReconnectRequest {
+                    let tc_url = match status.get_extra("tcUrl") {
+                        Some(Value::String(tc_url)) => Some(tc_url.clone()),
+                        _ => None,
+                    };
+                    self.context.reconnect_requested = Some(tc_url);
+                }
+
+                let closed_by_server =
+                    matches!(status.code, NetStatusCode::ConnectClosed | NetStatusCode::ConnectIdleTimeout);
+
+                self.dispatch_event(NetConnectionEvent::Status { stream_id: 0, status });
+
+                if closed_by_server {
+                    self.context.transaction_manager.fail_all("Connection closed by the server");
+                    self.close_transport();
+                    return Ok(());
+                }
+
+                // only an onStatus handler of the user turns it into a call
+                if !self.context.handlers.has_handler("onStatus") {
+                    return Ok(());
+                }
+            }
+        }
+
+        if command.procedure_name == "onStatus" && header.message_stream_id != 0 {
+            self.process_stream_status(header.message_stream_id, command);
+            return Ok(());
+        }
+
+        // handlers registered by the user take over the bandwidth check
+        if !self.context.handlers.has_handler(&command.procedure_name) {
+            match command.procedure_name.as_str() {
+                "onBWCheck" | "_onbwcheck" => return self.process_bandwidth_check(command, header),
+                "onBWDone" | "_onbwdone" => return self.process_bandwidth_done(command),
+                _ => {}
+            }
+        }
+
+        self.process_call(command, header)
     }
 
-    fn process_shared_object(&mut self, shared_object: Arc<Mutex<SharedObject>>) {
-        let mut shared_object = shared_object.lock().unwrap();
-        shared_object.process_events();
+    fn process_bandwidth_check(&mut self, command: AMFCommandMessage, header: RTMPMessageHeader) -> std::io::Result<()> {
+        let count = self
+            .context
+            .bandwidth_check
+            .on_check(&command.optional_arguments, Instant::now());
+
+        if command.transaction_id == 0 {
+            return Ok(());
+        }
+
+        self.send_command(
+            AMFCommandMessage {
+                procedure_name: "_result".to_string(),
+                transaction_id: command.transaction_id,
+                command_object: None,
+                optional_arguments: vec![Value::Number(count as f64)],
+            },
+            RTMPMessageHeader {
+                timestamp: 0,
+                message_stream_id: header.message_stream_id,
+            },
+        )
     }
 
-    pub fn process_messages<'b>(&mut self) -> std::io::Result<()> {
-        let rtmp_message;
+    /// A bare `onBWDone` right after connect asks the client to start a check.
+    fn process_bandwidth_done(&mut self, command: AMFCommandMessage) -> std::io::Result<()> {
+        let bandwidth_check = &mut self.context.bandwidth_check;
 
-        rtmp_message = RTMPReader::read(&mut self.context)?;
+        if command.optional_arguments.is_empty() && bandwidth_check.count == 0 && !bandwidth_check.requested {
+            bandwidth_check.requested = true;
+            self.call("_checkbw", None, Vec::new(), None)?;
+            return Ok(());
+        }
 
-        match rtmp_message {
-            RTMPMessageType::SetChunkSize(set_chunk_size) => self.process_set_chunk_size(set_chunk_size),
-            RTMPMessageType::WindowAcknowledgementSize(window_ack_size) => self.process_window_ack_size(window_ack_size),
-            RTMPMessageType::SetPeerBandwidth(peer_bandwidth) => self.process_set_peer_bandwidth(peer_bandwidth),
-            RTMPMessageType::UserControlMessage(user_control_message) => self.process_user_control_message(user_control_message),
-            RTMPMessageType::AMF0Command(command) => self.process_amf0_command(command),
-            RTMPMessageType::AMF3SharedObject(shared_object) => self.process_shared_object(shared_object),
-        };
+        bandwidth_check.on_done(&command.optional_arguments);
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::transport::tcp_transport::TcpTransport;
+    /// Asks the server to run its bandwidth check, the result is available
+    /// from `bandwidth` once it sent `onBWDone`.
+    pub fn check_bandwidth(&mut self) -> std::io::Result<()> {
+        self.context.bandwidth_check.requested = true;
+        self.call("checkBandwidth", None, Vec::new(), None)?;
 
-    #[test]
-    fn test_connect() {
-        let transport: TcpTransport = TcpTransport::new();
-        let mut connection = NetConnection::new(transport);
+        Ok(())
+    }
 
-        let result = connection.connect(
-            "rtmp://127.0.0.1/",
-            |properties, information| {
-                println!("properties: {:?}", properties);
-                println!("information: {:?}", information);
-            },
-        );
+    /// Result of the last bandwidth check.
+    pub fn bandwidth(&self) -> Option<Bandwidth> {
+        self.context.bandwidth_check.result
+    }
 
-        assert!(result.is_ok());
+    fn process_shared_object(&mut self, shared_object: Arc<Mutex<SharedObject>>) {
+        let (name, events) = {
+            let mut shared_object = shared_object.lock().unwrap();
+            let events = shared_object.events.clone();
+            shared_object.process_events();
 
-        loop {
-            connection.process_messages().unwrap();
-        }
+            (shared_object.name.clone(), events)
+        };
+
+        self.dispatch_event(NetConnectionEvent::SharedObjectSync { name, events });
+    }
+
+    /// Sends a message with a caller provided timestamp and message stream.
+    pub fn send_message(&mut self, message: RTMPMessageType, header: RTMPMessageHeader) -> std::io::Result<()> {
+        RTMPWriter::write_with_header(message, header, &mut self.context)
+    }
+
+    /// Sends a message of any type as is, without looking at its payload.
+    /// Useful for proxies and for vendor specific message types.
+    pub fn send_raw(&mut self, type_id: u8, stream_id: u32, timestamp: u32, payload: Vec<u8>) -> std::io::Result<()> {
+        RTMPWriter::write(
+            RTMPMessageType::Raw {
+                type_id,
+                stream_id,
+                timestamp,
+                payload,
+            },
+            &mut self.context,
+        )
+    }
+
+    /// Timeout of calls waiting for an answer, `None` waits forever. Expired
+    /// calls fail with NetConnection.Call.Failed while processing messages,
+    /// which then returns a TimedOut error. Transports that can't wait for
+    /// data with a timeout need a read timeout of their own for this.
+    pub fn set_call_timeout(&mut self, timeout: Option<Duration>) {
+        self.context.transaction_manager.default_timeout = timeout;
+    }
+
+    /// Most calls that may wait for an answer at the same time.
+    pub fn set_max_pending_calls(&mut self, max_pending_calls: usize) {
+        self.context.transaction_manager.max_pending_transactions = max_pending_calls;
+    }
+
+    pub fn pending_calls(&self) -> usize {
+        self.context.transaction_manager.pending_transactions()
+    }
+
+    /// Stops waiting for the answer to a call, none of its callbacks run.
+    pub fn cancel_call(&mut self, transaction_id: u32) -> bool {
+        self.context.transaction_manager.cancel_transaction(transaction_id)
+    }
+
+    pub fn check_call_timeouts(&mut self) {
+        self.context.transaction_manager.check_timeouts(Instant::now());
+    }
+
+    fn is_disconnect_error(error: &std::io::Error) -> bool {
+        matches!(
+            error.kind(),
+            ErrorKind::UnexpectedEof
+                | ErrorKind::BrokenPipe
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+        )
+    }
+
+    /// Fails the pending calls when an error means the transport is gone.
+    fn process_io_error(&mut self, error: std::io::Error) -> std::io::Error {
+        if NetConnection::<T>::is_disconnect_error(&error) {
+            self.context.transaction_manager.fail_all("Connection closed");
+            self.close_transport();
+        }
+
+        error
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.context.state
+    }
+
+    /// Moves to the next lifecycle state, dispatching StateChanged, and
+    /// Closed when a connection that was up goes away.
+    fn set_state(&mut self, next: ConnectionState) -> std::io::Result<()> {
+        let current = self.context.state;
+        if current == next {
+            return Ok(());
+        }
+
+        if !current.can_transition_to(next) {
+            return Err(ProtocolError::InvalidStateTransition { from: current, to: next }.into());
+        }
+
+        self.context.state = next;
+        self.dispatch_event(NetConnectionEvent::StateChanged { from: current, to: next });
+
+        if next == ConnectionState::Closed && matches!(current, ConnectionState::Connected | ConnectionState::Closing) {
+            self.dispatch_event(NetConnectionEvent::Closed);
+        }
+
+        Ok(())
+    }
+
+    /// Shuts the transport down, whatever state the connection is in.
+    fn close_transport(&mut self) {
+        let _ = self.context.transport.disconnect();
+
+        if self.context.state.is_open() {
+            let _ = self.set_state(ConnectionState::Closed);
+        }
+    }
+
+    /// Deletes the streams, releases the shared objects and shuts the
+    /// transport down. Calls still waiting for an answer fail.
+    pub fn close(&mut self) -> std::io::Result<()> {
+        match self.context.state {
+            ConnectionState::Connected => self.set_state(ConnectionState::Closing)?,
+            ConnectionState::Disconnected | ConnectionState::Closing | ConnectionState::Closed => return Ok(()),
+            // nothing to release before connect was answered
+            ConnectionState::Handshaking | ConnectionState::Connecting => {}
+        }
+
+        // a transport that already dropped must not stop the rest of the close
+        if self.context.state == ConnectionState::Closing {
+            let net_streams: Vec<Arc<Mutex<NetStream>>> = self.context.net_streams.clone();
+            for net_stream in net_streams {
+                let _ = NetStream::delete(net_stream, self);
+            }
+
+            let shared_objects: Vec<Arc<Mutex<SharedObject>>> = self.context.shared_objects.values().cloned().collect();
+            for shared_object in shared_objects {
+                let _ = SharedObject::close(shared_object, self);
+            }
+
+            let _ = RTMPWriter::flush(&mut self.context);
+        }
+
+        self.context.transaction_manager.fail_all("Connection closed");
+        self.context.connect_deadline = None;
+        self.close_transport();
+
+        Ok(())
+    }
+
+    /// Reconnects on its own whenever the transport drops, or the server asks
+    /// for it. `None` turns it off, which is the default.
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: Option<ReconnectPolicy>) {
+        self.context.reconnect_policy = reconnect_policy;
+    }
+
+    /// Connects again with the tcUrl and options of the last connect, then
+    /// uses every shared object again and replays play and publish on new
+    /// streams. Calls still waiting for an answer fail, as their answer is
+    /// lost with the old connection.
+    pub fn reconnect(&mut self) -> std::io::Result<()> {
+        let (tc_url, options) = self.context.connect_request.clone().ok_or(std::io::Error::new(
+            ErrorKind::NotConnected,
+            "connect was never called",
+        ))?;
+
+        self.reconnect_to(&tc_url, options)
+    }
+
+    fn reconnect_to(&mut self, tc_url: &str, options: ConnectOptions) -> std::io::Result<()> {
+        let reconnect_policy = self.context.reconnect_policy.clone().unwrap_or_default();
+
+        self.close_transport();
+        self.context.transaction_manager.fail_all("Connection closed");
+        self.context.connect_request = Some((tc_url.to_string(), options.clone()));
+
+        self.context.reconnecting = true;
+        let result = self.reconnect_with_policy(tc_url, &options, &reconnect_policy);
+        self.context.reconnecting = false;
+
+        result
+    }
+
+    fn reconnect_with_policy(
+        &mut self,
+        tc_url: &str,
+        options: &ConnectOptions,
+        reconnect_policy: &ReconnectPolicy,
+    ) -> std::io::Result<()> {
+        let mut attempt = 0;
+
+        loop {
+            std::thread::sleep(reconnect_policy.delay(attempt));
+
+            let error = match self.connect_to_endpoints(tc_url, options) {
+                Ok(outcome) if outcome.result == TransactionResult::Result => break,
+                Ok(_) => std::io::Error::new(ErrorKind::ConnectionRefused, "Server rejected the reconnect"),
+                Err(error) => error,
+            };
+
+            attempt += 1;
+            if !reconnect_policy.allows_attempt(attempt) {
+                return Err(error);
+            }
+
+            let _ = self.context.transport.disconnect();
+        }
+
+        self.restore_session()
+    }
+
+    /// Brings shared objects and streams back after a reconnect.
+    fn restore_session(&mut self) -> std::io::Result<()> {
+        let shared_objects: Vec<Arc<Mutex<SharedObject>>> = self.context.shared_objects.values().cloned().collect();
+
+        for shared_object in shared_objects {
+            {
+                let mut shared_object = shared_object.lock().unwrap();
+                shared_object.clear_events();
+                shared_object.version = 0;
+                shared_object.use_success = false;
+            }
+
+            SharedObject::connect(shared_object, self)?;
+        }
+
+        // stream ids belong to the old connection, every stream is created anew;
+        // they stay registered so a failure here doesn't lose them
+        let net_streams: Vec<Arc<Mutex<NetStream>>> = self.context.net_streams.clone();
+        for net_stream in &net_streams {
+            net_stream.lock().unwrap().stream_id = None;
+            NetStream::create(net_stream.clone(), self)?;
+        }
+
+        let is_pending = |net_stream: &Arc<Mutex<NetStream>>| net_stream.lock().unwrap().stream_id.is_none();
+        while net_streams.iter().any(is_pending) && self.pending_calls() > 0 {
+            self.process_message()?;
+        }
+
+        for net_stream in net_streams {
+            if !is_pending(&net_stream) {
+                NetStream::replay(net_stream, self)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next thing that happened on the connection, reading messages
+    /// until one produces an event. Once the connection is closed and its
+    /// last events are returned, it fails with NotConnected.
+    pub fn poll_event(&mut self) -> std::io::Result<NetConnectionEvent> {
+        loop {
+            if let Some(event) = self.context.events.pop_front() {
+                return Ok(event);
+            }
+
+            if let Err(error) = self.process_messages() {
+                // the events of the failure itself, like Closed, come first
+                if self.context.events.is_empty() {
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    /// Events produced so far that `poll_event` hasn't returned yet, the last
+    /// `MAX_QUEUED_EVENTS` at most.
+    pub fn take_events(&mut self) -> Vec<NetConnectionEvent> {
+        self.context.events.drain(..).collect()
+    }
+
+    /// Reads and handles one message, returning its timestamp and message
+    /// stream. With a reconnect policy, a dropped transport is reconnected
+    /// before reading on. Events it produces queue up for `poll_event`.
+    pub fn process_messages(&mut self) -> std::io::Result<RTMPMessageHeader> {
+        loop {
+            // closed on purpose, by us or the server, never reconnects on its own
+            if !self.context.state.is_open() {
+                return Err(std::io::Error::new(ErrorKind::NotConnected, "Connection is closed"));
+            }
+
+            let result = self.process_message();
+            let can_reconnect = self.context.reconnect_policy.is_some() && !self.context.reconnecting;
+
+            match result {
+                Err(error) if can_reconnect && NetConnection::<T>::is_disconnect_error(&error) => self.reconnect()?,
+                result => {
+                    if let Some(tc_url) = self.context.reconnect_requested.take() {
+                        if can_reconnect {
+                            self.process_reconnect_request(tc_url)?;
+                        }
+                    }
+
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn process_reconnect_request(&mut self, tc_url: Option<String>) -> std::io::Result<()> {
+        let (last_tc_url, options) = match self.context.connect_request.clone() {
+            Some(connect_request) => connect_request,
+            None => return Ok(()),
+        };
+
+        self.reconnect_to(&tc_url.unwrap_or(last_tc_url), options)
+    }
+
+    /// Waits for data no longer than the nearest call or connect deadline, so
+    /// they fire even when the server stays silent. Returns false once the
+    /// deadline passed without data.
+    fn wait_for_message(&mut self) -> std::io::Result<bool> {
+        let deadline = [
+            self.context.transaction_manager.next_deadline(),
+            self.context.connect_deadline,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        // messages split off an aggregate are already there
+        let deadline = match deadline {
+            Some(deadline) if self.context.pending_messages.is_empty() => deadline,
+            _ => return Ok(true),
+        };
+
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match self.context.transport.wait_for_data(wait) {
+            Ok(ready) => Ok(ready),
+            Err(error) => Err(self.process_io_error(error)),
+        }
+    }
+
+    fn process_message(&mut self) -> std::io::Result<RTMPMessageHeader> {
+        self.check_call_timeouts();
+
+        if !self.wait_for_message()? {
+            self.check_call_timeouts();
+            return Err(std::io::Error::new(ErrorKind::TimedOut, "No message came in before the deadline"));
+        }
+
+        let (header, rtmp_message) = match RTMPReader::read(&mut self.context) {
+            Ok(message) => message,
+            Err(error) => {
+                if matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) {
+                    self.check_call_timeouts();
+                }
+
+                return Err(self.process_io_error(error));
+            }
+        };
+
+        match rtmp_message {
+            RTMPMessageType::SetChunkSize(set_chunk_size) => self.process_set_chunk_size(set_chunk_size)?,
+            RTMPMessageType::WindowAcknowledgementSize(window_ack_size) => self.process_window_ack_size(window_ack_size),
+            RTMPMessageType::SetPeerBandwidth(peer_bandwidth) => self.process_set_peer_bandwidth(peer_bandwidth),
+            RTMPMessageType::UserControlMessage(user_control_message) => {
+                self.process_user_control_message(user_control_message)?
+            }
+            RTMPMessageType::AMF0Command(command) => self.process_command(command, header)?,
+            RTMPMessageType::AMF3Command(command) => self.process_command(command, header)?,
+            RTMPMessageType::AMF3SharedObject(shared_object) => self.process_shared_object(shared_object),
+            RTMPMessageType::Data(data) => {
+                self.dispatch_stream_event(header.message_stream_id, NetStreamEvent::Data(data.clone()));
+                self.dispatch_event(NetConnectionEvent::Data {
+                    stream_id: header.message_stream_id,
+                    timestamp: header.timestamp,
+                    data,
+                });
+            }
+            RTMPMessageType::Audio(audio) => {
+                self.dispatch_stream_event(
+                    header.message_stream_id,
+                    NetStreamEvent::Audio { timestamp: header.timestamp, audio: audio.clone() },
+                );
+                self.dispatch_event(NetConnectionEvent::Audio {
+                    stream_id: header.message_stream_id,
+                    timestamp: header.timestamp,
+                    audio,
+                });
+            }
+            RTMPMessageType::Video(video) => {
+                self.dispatch_stream_event(
+                    header.message_stream_id,
+                    NetStreamEvent::Video { timestamp: header.timestamp, video: video.clone() },
+                );
+                self.dispatch_event(NetConnectionEvent::Video {
+                    stream_id: header.message_stream_id,
+                    timestamp: header.timestamp,
+                    video,
+                });
+            }
+            RTMPMessageType::Raw { type_id, stream_id, timestamp, payload } => {
+                self.dispatch_event(NetConnectionEvent::Raw { type_id, stream_id, timestamp, payload });
+            }
+        };
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::packets::MessageTypeId;
+    use crate::chunk::reader::RTMPDechunker;
+    use crate::context::{allocate_net_connection_context, NetConnectionContext, DEFAULT_CHUNK_SIZE};
+    use crate::handshake::RTMP_PROTOCOL_VERSION;
+    use crate::net_connection::status::NetStatusLevel;
+    use crate::transport::memory_transport::MemoryTransport;
+    use crate::transport::tcp_transport::TcpTransport;
+    use crate::utils::tea::encrypt_token;
+    use crate::media::packets::{FrameType, VideoCodecId, VideoMessage, VideoTagHeader};
+    use crate::net_connection::packets::DataMessage;
+    use flash_lso::types::Element;
+    use std::sync::mpsc::Receiver;
+
+    #[test]
+    fn test_connect() {
+        let transport: TcpTransport = TcpTransport::new();
+        let mut connection = NetConnection::new(transport);
+
+        let result = connection.connect(
+            "rtmp://127.0.0.1/",
+            |properties, information| {
+                println!("properties: {:?}", properties);
+                println!("information: {:?}", information);
+            },
+        );
+
+        assert!(result.is_ok());
+
+        loop {
+            connection.process_messages().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_call_timeout_without_answer() {
+        let (client, _server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        connection.set_call_timeout(Some(Duration::from_millis(50)));
+
+        let failed = Rc::new(RefCell::new(false));
+        let call_failed = failed.clone();
+        connection
+            .call(
+                "silence",
+                None,
+                Vec::new(),
+                Some(Responder::with_error(|_, _| {}, move |_, _| *call_failed.borrow_mut() = true)),
+            )
+            .unwrap();
+
+        // the server never answers, the deadline bounds the read
+        let started = Instant::now();
+        let error = connection.process_message().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(*failed.borrow());
+    }
+
+    /// Takes the next connection of the client through the handshake.
+    fn accept(listener: &Receiver<MemoryTransport>) -> NetConnectionContext<MemoryTransport> {
+        let mut transport = listener.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // S2 echoes C1 whole
+        let c0_c1 = transport.read_data(1537).unwrap();
+        let mut s0_s1_s2 = vec![RTMP_PROTOCOL_VERSION];
+        s0_s1_s2.extend([0u8; 1536]);
+        s0_s1_s2.extend(&c0_c1[1..]);
+        transport.write_data(s0_s1_s2).unwrap();
+        transport.read_data(1536).unwrap();
+
+        allocate_net_connection_context(transport)
+    }
+
+    /// Answers connect and createStream until `last` comes in, returning the
+    /// commands and shared object messages received.
+    fn serve(server: &mut NetConnectionContext<MemoryTransport>, last: &str) -> Vec<String> {
+        let mut received = Vec::new();
+
+        loop {
+            let name = match RTMPReader::read(server).unwrap() {
+                (_, RTMPMessageType::AMF0Command(command)) => {
+                    let result = match command.procedure_name.as_str() {
+                        "connect" => Some(NetStatus::new(NetStatusCode::ConnectSuccess, NetStatusLevel::Status, "").to_value()),
+                        "createStream" => Some(Value::Number(1.0)),
+                        _ => None,
+                    };
+
+                    if let Some(result) = result {
+                        let answer = AMFCommandMessage {
+                            procedure_name: "_result".to_string(),
+                            transaction_id: command.transaction_id,
+                            command_object: None,
+                            optional_arguments: vec![result],
+                        };
+                        RTMPWriter::write(RTMPMessageType::AMF0Command(answer), server).unwrap();
+                    }
+
+                    command.procedure_name
+                }
+                (_, RTMPMessageType::AMF3SharedObject(_)) => "sharedObject".to_string(),
+                (_, RTMPMessageType::Raw { type_id: 16, .. }) => "sharedObject".to_string(),
+                _ => continue,
+            };
+
+            received.push(name.clone());
+            if name == last {
+                return received;
+            }
+        }
+    }
+
+    #[test]
+    fn test_reconnect_request() {
+        let (transport, listener) = MemoryTransport::listen();
+
+        let server = std::thread::spawn(move || {
+            let mut first = accept(&listener);
+            let first_session = serve(&mut first, "play");
+
+            let reconnect_request = AMFCommandMessage {
+                procedure_name: "onStatus".to_string(),
+                transaction_id: 0,
+                command_object: None,
+                optional_arguments: vec![
+                    NetStatus::new(NetStatusCode::ConnectReconnectRequest, NetStatusLevel::Status, "").to_value(),
+                ],
+            };
+            RTMPWriter::write(RTMPMessageType::AMF0Command(reconnect_request), &mut first).unwrap();
+
+            let mut second = accept(&listener);
+            let second_session = serve(&mut second, "play");
+
+            (first_session, second_session)
+        });
+
+        let mut connection = NetConnection::new(transport);
+        connection.set_reconnect_policy(Some(ReconnectPolicy::new().initial_delay(Duration::ZERO).jitter(0.0)));
+        connection.connect("rtmp://localhost/live", |_, _| {}).unwrap();
+
+        let shared_object = SharedObject::new_shared_object("chat".to_string(), false);
+        SharedObject::connect(shared_object, &mut connection).unwrap();
+
+        let net_stream = NetStream::new_net_stream();
+        NetStream::create(net_stream.clone(), &mut connection).unwrap();
+        while net_stream.lock().unwrap().stream_id.is_none() {
+            connection.process_messages().unwrap();
+        }
+        NetStream::play(net_stream.clone(), &mut connection, "live", -2.0, -1.0, true).unwrap();
+
+        // handles the request by connecting again and restoring the session
+        connection.process_messages().unwrap();
+
+        let (first_session, second_session) = server.join().unwrap();
+        assert_eq!(first_session, vec!["connect", "sharedObject", "createStream", "play"]);
+        assert_eq!(second_session, vec!["connect", "sharedObject", "createStream", "play"]);
+
+        assert_eq!(connection.context.state, ConnectionState::Connected);
+        assert_eq!(connection.context.net_streams.len(), 1);
+        assert_eq!(net_stream.lock().unwrap().stream_id, Some(1));
+    }
+
+    fn rejection(ex: Vec<Element>) -> ConnectOutcome {
+        let mut status = NetStatus::error(NetStatusCode::ConnectRejected, "Connection failed");
+        status.extra.push(Element {
+            name: "ex".to_string(),
+            value: Rc::new(Value::Object(ex, None)),
+        });
+
+        ConnectOutcome {
+            result: TransactionResult::Error,
+            command_object: Value::Null,
+            arguments: vec![status.to_value()],
+        }
+    }
+
+    fn element(name: &str, value: Value) -> Element {
+        Element {
+            name: name.to_string(),
+            value: Rc::new(value),
+        }
+    }
+
+    #[test]
+    fn test_get_redirect() {
+        let redirect = rejection(vec![
+            element("code", Value::Number(302.0)),
+            element("redirect", Value::String("rtmp://edge2/live".to_string())),
+        ]);
+        assert_eq!(
+            NetConnection::<MemoryTransport>::get_redirect(&redirect).as_deref(),
+            Some("rtmp://edge2/live")
+        );
+
+        let other_code = rejection(vec![
+            element("code", Value::Number(403.0)),
+            element("redirect", Value::String("rtmp://edge2/live".to_string())),
+        ]);
+        assert_eq!(NetConnection::<MemoryTransport>::get_redirect(&other_code), None);
+
+        let mut accepted = rejection(vec![element("code", Value::Number(302.0))]);
+        accepted.result = TransactionResult::Result;
+        assert_eq!(NetConnection::<MemoryTransport>::get_redirect(&accepted), None);
+    }
+
+    #[test]
+    fn test_check_redirect() {
+        let check = NetConnection::<MemoryTransport>::check_redirect;
+        let visited = vec!["rtmp://edge1/live".to_string()];
+
+        assert_eq!(check(&visited, "rtmp://edge2/live", 1).unwrap().host, "edge2");
+
+        let error = check(&visited, "rtmp://edge2/live", 0).unwrap_err();
+        assert_eq!(error.to_string(), ProtocolError::TooManyRedirects { limit: 0 }.to_string());
+
+        let error = check(&visited, "rtmp://edge1/live", 1).unwrap_err();
+        assert_eq!(error.to_string(), ProtocolError::RedirectLoop("rtmp://edge1/live".to_string()).to_string());
+
+        let error = check(&visited, "http://edge2/live", 1).unwrap_err();
+        assert_eq!(error.to_string(), ProtocolError::UnsupportedProtocol("http".to_string()).to_string());
+    }
+
+    #[test]
+    fn test_connect_unsupported_protocol() {
+        let (transport, _listener) = MemoryTransport::listen();
+        let mut connection = NetConnection::new(transport);
+
+        let error = connection.connect("rtmps://localhost/live", |_, _| {}).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_attempt_timeout_restores_timeout() {
+        let (mut transport, _listener) = MemoryTransport::listen();
+        transport.set_timeout(Some(Duration::from_secs(3))).unwrap();
+        let mut connection = NetConnection::new(transport);
+
+        let options = ConnectOptions::new().attempt_timeout(Duration::from_millis(100));
+        let responder = Responder::new(|_, _| {});
+        assert!(connection.connect_with("rtmps://localhost/live", options, responder).is_err());
+
+        assert_eq!(connection.context.transport.timeout(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_event_queue_limit() {
+        let (client, _server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+
+        connection.dispatch_event(NetConnectionEvent::Ping { timestamp: 0 });
+        for timestamp in 0..MAX_QUEUED_EVENTS as u32 {
+            let data = DataMessage {
+                handler: "onCuePoint".to_string(),
+                arguments: Vec::new(),
+                object_encoding: ObjectEncoding::AMF0,
+            };
+            connection.dispatch_event(NetConnectionEvent::Data { stream_id: 1, timestamp, data });
+        }
+        connection.dispatch_event(NetConnectionEvent::Closed);
+
+        // the oldest frames made room, the other events stay
+        let events = connection.take_events();
+        assert_eq!(events.len(), MAX_QUEUED_EVENTS);
+        assert!(matches!(events.first(), Some(NetConnectionEvent::Ping { .. })));
+        assert!(matches!(events[1], NetConnectionEvent::Data { timestamp: 2, .. }));
+        assert!(matches!(events.last(), Some(NetConnectionEvent::Closed)));
+    }
+
+    #[test]
+    fn test_raw_passthrough() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        let mut server = allocate_net_connection_context(server);
+
+        let vendor_message = RTMPMessageType::Raw {
+            type_id: 0x17,
+            stream_id: 1,
+            timestamp: 40,
+            payload: vec![1, 2, 3, 4],
+        };
+        RTMPWriter::write(vendor_message, &mut server).unwrap();
+        connection.process_messages().unwrap();
+
+        let (type_id, stream_id, timestamp, payload) = match connection.take_events().pop() {
+            Some(NetConnectionEvent::Raw { type_id, stream_id, timestamp, payload }) => {
+                (type_id, stream_id, timestamp, payload)
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert_eq!((type_id, stream_id, timestamp), (0x17, 1, 40));
+
+        connection.send_raw(type_id, stream_id, timestamp, payload).unwrap();
+        match RTMPReader::read(&mut server).unwrap() {
+            (header, RTMPMessageType::Raw { type_id: 0x17, payload, .. }) => {
+                assert_eq!(header, RTMPMessageHeader { timestamp: 40, message_stream_id: 1 });
+                assert_eq!(payload, vec![1, 2, 3, 4]);
+            }
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    fn keyframe(size: usize) -> RTMPMessageType {
+        RTMPMessageType::Video(VideoMessage {
+            header: VideoTagHeader {
+                frame_type: FrameType::KeyFrame,
+                codec_id: VideoCodecId::SorensonH263,
+                avc_packet_type: None,
+                composition_time: None,
+            },
+            data: vec![0; size],
+        })
+    }
+
+    #[test]
+    fn test_command_ahead_of_queued_video() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        let mut server = allocate_net_connection_context(server);
+
+        let header = RTMPMessageHeader { timestamp: 0, message_stream_id: 1 };
+        connection.queue_message(keyframe(1000), header).unwrap();
+
+        // part of the keyframe is out when the command comes
+        assert!(!connection.flush_chunks(2).unwrap());
+        connection.call("ping", None, Vec::new(), None).unwrap();
+        assert!(connection.flush_chunks(usize::MAX).unwrap());
+
+        match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::AMF0Command(command)) => assert_eq!(command.procedure_name, "ping"),
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+        match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::Video(video)) => assert_eq!(video.data.len(), 1000),
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_chunk_size_change_behind_queued_video() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        let mut server = allocate_net_connection_context(server);
+
+        let header = RTMPMessageHeader { timestamp: 0, message_stream_id: 1 };
+        connection.queue_message(keyframe(1000), header).unwrap();
+        connection.flush_chunks(1).unwrap();
+
+        connection.set_chunk_size(4096).unwrap();
+        assert_eq!(connection.out_chunk_size(), 4096);
+        connection.flush().unwrap();
+
+        // the rest of the keyframe comes in chunks of the new size
+        match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::SetChunkSize(set_chunk_size)) => server.in_chunk_size = set_chunk_size.size,
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+        match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::Video(video)) => assert_eq!(video.data.len(), 1000),
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    /// Connects asking for `requested` while the server answers with
+    /// `accepted`, then makes a call and returns it as it went over the wire.
+    fn negotiated_call(requested: ObjectEncoding, accepted: f64) -> (ObjectEncoding, RTMPMessage) {
+        let (transport, listener) = MemoryTransport::listen();
+
+        let server = std::thread::spawn(move || {
+            let mut server = accept(&listener);
+
+            let transaction_id = loop {
+                if let (_, RTMPMessageType::AMF0Command(command)) = RTMPReader::read(&mut server).unwrap() {
+                    break command.transaction_id;
+                }
+            };
+
+            let mut status = NetStatus::new(NetStatusCode::ConnectSuccess, NetStatusLevel::Status, "");
+            status.extra.push(element("objectEncoding", Value::Number(accepted)));
+            let answer = AMFCommandMessage {
+                procedure_name: "_result".to_string(),
+                transaction_id,
+                command_object: None,
+                optional_arguments: vec![status.to_value()],
+            };
+            RTMPWriter::write(RTMPMessageType::AMF0Command(answer), &mut server).unwrap();
+
+            loop {
+                let message = RTMPDechunker::read_chunks(&mut server).unwrap();
+                if message.message_type_id == MessageTypeId::CommandAMF0 as u8
+                    || message.message_type_id == MessageTypeId::CommandAMF3 as u8
+                {
+                    return message;
+                }
+            }
+        });
+
+        let mut connection = NetConnection::new(transport);
+        connection.set_object_encoding(requested);
+        connection.connect("rtmp://localhost/live", |_, _| {}).unwrap();
+        connection.call("check", None, vec![Value::Number(1.5)], None).unwrap();
+
+        (connection.object_encoding(), server.join().unwrap())
+    }
+
+    #[test]
+    fn test_amf3_negotiated() {
+        let (object_encoding, message) = negotiated_call(ObjectEncoding::AMF3, 3.0);
+        assert_eq!(object_encoding, ObjectEncoding::AMF3);
+        assert_eq!(message.message_type_id, MessageTypeId::CommandAMF3 as u8);
+
+        // format byte, then the argument switched over to AMF3 as a double
+        assert_eq!(message.payload[0], 0x00);
+        let mut argument = vec![0x11, 0x05];
+        argument.extend(1.5f64.to_be_bytes());
+        assert!(message.payload.ends_with(&argument));
+    }
+
+    #[test]
+    fn test_amf3_refused() {
+        let (object_encoding, message) = negotiated_call(ObjectEncoding::AMF3, 0.0);
+        assert_eq!(object_encoding, ObjectEncoding::AMF0);
+        assert_eq!(message.message_type_id, MessageTypeId::CommandAMF0 as u8);
+
+        // a server offering AMF3 unasked doesn't switch either
+        let (object_encoding, message) = negotiated_call(ObjectEncoding::AMF0, 3.0);
+        assert_eq!(object_encoding, ObjectEncoding::AMF0);
+        assert_eq!(message.message_type_id, MessageTypeId::CommandAMF0 as u8);
+    }
+
+    fn connected_pair() -> (NetConnection<MemoryTransport>, NetConnectionContext<MemoryTransport>) {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+
+        (connection, allocate_net_connection_context(server))
+    }
+
+    fn received_command(server: &mut NetConnectionContext<MemoryTransport>) -> (RTMPMessageHeader, AMFCommandMessage) {
+        match RTMPReader::read(server).unwrap() {
+            (header, RTMPMessageType::AMF0Command(command)) => (header, command),
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_call_without_responder() {
+        let (mut connection, mut server) = connected_pair();
+
+        assert_eq!(connection.call("log", None, vec![Value::Bool(true)], None).unwrap(), 0);
+        assert_eq!(connection.pending_calls(), 0);
+
+        let (header, command) = received_command(&mut server);
+        assert_eq!(header.message_stream_id, 0);
+        assert_eq!(command.procedure_name, "log");
+        assert_eq!(command.transaction_id, 0);
+        assert_eq!(command.optional_arguments, vec![Value::Bool(true)]);
+    }
+
+    #[test]
+    fn test_call_on_stream() {
+        let (mut connection, mut server) = connected_pair();
+
+        connection.call_on_stream(3, "seek", None, vec![Value::Number(0.0)], None).unwrap();
+
+        let (header, command) = received_command(&mut server);
+        assert_eq!(header.message_stream_id, 3);
+        assert_eq!(command.procedure_name, "seek");
+    }
+
+    #[test]
+    fn test_call_result() {
+        let (mut connection, mut server) = connected_pair();
+
+        let answer: Rc<RefCell<Option<Vec<Value>>>> = Rc::new(RefCell::new(None));
+        let result_answer = answer.clone();
+        let transaction_id = connection
+            .call(
+                "add",
+                None,
+                vec![Value::Number(1.0), Value::Number(2.0)],
+                Some(Responder::new(move |_, arguments| *result_answer.borrow_mut() = Some(arguments.to_vec()))),
+            )
+            .unwrap();
+        assert_ne!(transaction_id, 0);
+        assert_eq!(connection.pending_calls(), 1);
+
+        let (_, command) = received_command(&mut server);
+        assert_eq!(command.transaction_id, transaction_id);
+
+        let result = AMFCommandMessage {
+            procedure_name: "_result".to_string(),
+            transaction_id,
+            command_object: None,
+            optional_arguments: vec![Value::Number(3.0)],
+        };
+        RTMPWriter::write(RTMPMessageType::AMF0Command(result), &mut server).unwrap();
+        connection.process_messages().unwrap();
+
+        assert_eq!(*answer.borrow(), Some(vec![Value::Number(3.0)]));
+        assert_eq!(connection.pending_calls(), 0);
+    }
+
+    #[test]
+    fn test_call_send_failure() {
+        let (mut connection, server) = connected_pair();
+        drop(server);
+
+        let responder = Responder::new(|_, _| panic!("the call never went out"));
+        assert!(connection.call("add", None, Vec::new(), Some(responder)).is_err());
+        assert_eq!(connection.pending_calls(), 0);
+    }
+
+    /// Connects with a shared secret to a server sending a secureToken
+    /// challenge, returns how connect went and the name and argument of the
+    /// command sent after it.
+    fn secure_token_connect(challenge: &[u8]) -> (std::io::Result<()>, Option<(String, String)>) {
+        let (transport, listener) = MemoryTransport::listen();
+        let challenge = encrypt_token("mySharedSecret", challenge);
+
+        let server = std::thread::spawn(move || {
+            let mut server = accept(&listener);
+
+            let transaction_id = received_command(&mut server).1.transaction_id;
+            let mut status = NetStatus::new(NetStatusCode::ConnectSuccess, NetStatusLevel::Status, "");
+            status.extra.push(element("secureToken", Value::String(challenge)));
+            let answer = AMFCommandMessage {
+                procedure_name: "_result".to_string(),
+                transaction_id,
+                command_object: None,
+                optional_arguments: vec![status.to_value()],
+            };
+            RTMPWriter::write(RTMPMessageType::AMF0Command(answer), &mut server).unwrap();
+
+            match RTMPReader::read(&mut server) {
+                Ok((_, RTMPMessageType::AMF0Command(command))) => match command.optional_arguments.as_slice() {
+                    [Value::String(response)] => Some((command.procedure_name, response.clone())),
+                    arguments => panic!("unexpected arguments {:?}", arguments),
+                },
+                _ => None,
+            }
+        });
+
+        let mut connection = NetConnection::new(transport);
+        let options = ConnectOptions::new().secure_token("mySharedSecret");
+        let result = connection.connect_with("rtmp://localhost/live", options, Responder::new(|_, _| {}));
+        drop(connection);
+
+        (result, server.join().unwrap())
+    }
+
+    #[test]
+    fn test_secure_token_response() {
+        let (result, command) = secure_token_connect(b"a1b2c3d4e5f6g7h8");
+        assert!(result.is_ok());
+
+        let (procedure_name, response) = command.unwrap();
+        assert_eq!(procedure_name, "secureTokenResponse");
+        assert_eq!(response, "a1b2c3d4e5f6g7h8");
+    }
+
+    #[test]
+    fn test_secure_token_not_text() {
+        let (result, command) = secure_token_connect(&[0xff, 0xfe, 0xfd, 0xfc]);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(command.is_none());
+    }
+
+    #[test]
+    fn test_set_chunk_size() {
+        let (mut connection, mut server) = connected_pair();
+
+        connection.set_chunk_size(256).unwrap();
+        assert_eq!(connection.out_chunk_size(), 256);
+        connection.call("log", None, vec![Value::String("x".repeat(200))], None).unwrap();
+
+        // announced in a chunk of the previous size, what follows uses the new one
+        match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::SetChunkSize(set_chunk_size)) => {
+                assert_eq!(set_chunk_size.size, 256);
+                server.in_chunk_size = set_chunk_size.size;
+            }
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+        assert_eq!(received_command(&mut server).1.procedure_name, "log");
+    }
+
+    #[test]
+    fn test_invalid_chunk_size() {
+        let (mut connection, mut server) = connected_pair();
+
+        assert_eq!(connection.set_chunk_size(0).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(connection.set_chunk_size(MAX_CHUNK_SIZE + 1).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(connection.out_chunk_size(), DEFAULT_CHUNK_SIZE);
+
+        RTMPWriter::write(RTMPMessageType::SetChunkSize(SetChunkSize { size: 0 }), &mut server).unwrap();
+        assert_eq!(connection.process_messages().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(connection.in_chunk_size(), DEFAULT_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_peer_chunk_size() {
+        let (mut connection, mut server) = connected_pair();
+
+        RTMPWriter::write(RTMPMessageType::SetChunkSize(SetChunkSize { size: 4096 }), &mut server).unwrap();
+        connection.process_messages().unwrap();
+
+        assert_eq!(connection.in_chunk_size(), 4096);
+        assert_eq!(connection.out_chunk_size(), DEFAULT_CHUNK_SIZE);
     }
 }
@@ -1,5 +1,5 @@
 use flash_lso::types::Value;
-use crate::{chunk::packets::{ChunkImportance, MessageTypeId}, shared_object::SharedObject};
+use crate::{context::ObjectEncoding, media::packets::{AudioMessage, VideoMessage}, shared_object::SharedObject};
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
@@ -83,22 +83,53 @@ pub struct AMFCommandMessage {
     pub optional_arguments: Vec<Value>
 }
 
+#[derive(Debug, Clone)]
+pub struct DataMessage {
+    /// Name of the handler the data is meant for, like
+    /// onMetaData or @setDataFrame.
+    pub handler: String,
+
+    /// Values following the handler name
+    pub arguments: Vec<Value>,
+
+    /// Whether the message goes over the wire as AMF0 or AMF3
+    pub object_encoding: ObjectEncoding,
+}
+
 #[derive(Debug)]
 pub enum RTMPMessageType {
     SetChunkSize(SetChunkSize),
     UserControlMessage(UserControlMessage),
     WindowAcknowledgementSize(WindowAcknowledgementSize),
     SetPeerBandwidth(SetPeerBandwidth),
-    AMF0Command(AMFCommandMessage),  
+    AMF0Command(AMFCommandMessage),
+    AMF3Command(AMFCommandMessage),
     AMF3SharedObject(Arc<Mutex<SharedObject>>),
+    Data(DataMessage),
+    Audio(AudioMessage),
+    Video(VideoMessage),
+    /// A message passed through as is, for types that aren't parsed or unknown to us.
+    Raw {
+        type_id: u8,
+        stream_id: u32,
+        timestamp: u32,
+        payload: Vec<u8>,
+    },
+}
+
+/// Timestamp and message stream of a message, as carried by its chunk headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RTMPMessageHeader {
+    pub timestamp: u32,
+    pub message_stream_id: u32,
 }
 
 #[derive(Debug)]
 pub struct RTMPMessage {
     pub timestamp: u32,
-    pub message_type_id: MessageTypeId,
+    /// Raw message type, not necessarily one of `MessageTypeId`.
+    pub message_type_id: u8,
     pub message_stream_id: u32,
-    // todo: chunk_stream_id should be a u32
-    pub chunk_stream_id: ChunkImportance,
+    pub chunk_stream_id: u32,
     pub payload: Vec<u8>,
 }
\ No newline at end of file
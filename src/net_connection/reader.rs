@@ -2,12 +2,14 @@ use crate::{
     chunk::{
         packets::MessageTypeId,
         reader::RTMPDechunker,
-    }, context::NetConnectionContext, net_connection::{
+    }, context::{NetConnectionContext, ObjectEncoding, MAX_CHUNK_SIZE}, media::reader::MediaReader, net_connection::{
         packets::{
-            AMFCommandMessage, PeerBandwidthLimitType, RTMPMessageType, SetChunkSize, SetPeerBandwidth, WindowAcknowledgementSize
+            AMFCommandMessage, DataMessage, PeerBandwidthLimitType, RTMPMessageType, SetChunkSize, SetPeerBandwidth, WindowAcknowledgementSize
         },
+        aggregate_messages::reader::AggregateMessageReader,
+        packets::{RTMPMessage, RTMPMessageHeader},
         user_control_messages::reader::UserControlMessageReader
-    }, shared_object::reader::SharedObjectReader, transport::Transport, utils::nom::RTMPResult, errors::Error
+    }, shared_object::reader::SharedObjectReader, transport::Transport, utils::{amf::unwrap_amf3, nom::RTMPResult}, errors::Error
 };
 
 use flash_lso::{
@@ -45,14 +47,17 @@ impl RTMPReader {
     fn read_set_chunk_size(payload: &[u8]) -> RTMPResult<'_, SetChunkSize> {
         let (i, size) = be_u32(payload)?;
 
-        Ok((i, SetChunkSize { size }))
+        // the first bit must be zero, ignore it if a peer sets it anyway
+        Ok((i, SetChunkSize { size: size & MAX_CHUNK_SIZE }))
     }
 
-    fn read_amf0_command(payload: Vec<u8>) -> std::io::Result<AMFCommandMessage> {
+    /// Reads the AMF0 encoded body of a command. Values behind an
+    /// `avmplus-object` switch are returned without the AMF3 wrapper.
+    fn read_amf0_command(payload: &[u8]) -> std::io::Result<AMFCommandMessage> {
         let mut amf_decoder = AMF0Decoder::default();
 
         let (i, procedure_name) = amf_decoder
-            .parse_single_element(payload.as_slice())
+            .parse_single_element(payload)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse procedure name: {:?}", e)))?;
 
         let procedure_name = match Rc::try_unwrap(procedure_name) {
@@ -60,7 +65,7 @@ impl RTMPReader {
             Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to unwrap procedure name")),
         };
 
-        let procedure_name = match procedure_name {
+        let procedure_name = match unwrap_amf3(procedure_name) {
             Value::String(s) => s,
             _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid procedure name type")),
         };
@@ -74,16 +79,26 @@ impl RTMPReader {
             Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to unwrap transaction ID")),
         };
 
-        let transaction_id = match transaction_id {
+        let transaction_id = match unwrap_amf3(transaction_id) {
             Value::Number(n) => n as u32,
+            Value::Integer(n) => n as u32,
             _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid transaction ID type")),
         };
 
+        if i.is_empty() {
+            return Ok(AMFCommandMessage {
+                procedure_name,
+                transaction_id,
+                command_object: None,
+                optional_arguments: Vec::new(),
+            });
+        }
+
         let (mut i, command_object) = amf_decoder
             .parse_single_element(i)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse command object: {:?}", e)))?;
 
-        let command_object = (*command_object).clone();
+        let command_object = unwrap_amf3((*command_object).clone());
 
         let command_object = match command_object {
             Value::Null => None,
@@ -97,7 +112,7 @@ impl RTMPReader {
                 .parse_single_element(i)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse optional argument: {:?}", e)))?;
 
-            let optional_argument = (*optional_argument).clone();
+            let optional_argument = unwrap_amf3((*optional_argument).clone());
 
             optional_arguments.push(optional_argument);
 
@@ -112,42 +127,192 @@ impl RTMPReader {
         })
     }
 
-    pub fn read<'b, T: Transport>(context: &mut NetConnectionContext<T>) -> std::io::Result<RTMPMessageType> {
-        let message = RTMPDechunker::read_chunks(context)?;
+    pub(crate) fn read_amf0_data(payload: &[u8], object_encoding: ObjectEncoding) -> std::io::Result<DataMessage> {
+        let mut amf_decoder = AMF0Decoder::default();
 
-        let parsed_message = match message.message_type_id {
-            MessageTypeId::WindowAcknowledgementSize => {
-                let (_, window_acknowledgement_size) = RTMPReader::read_window_acknowledgement_size(message.payload.as_slice())
-                    .expect("Failed to parse window acknowledgement size");
-                RTMPMessageType::WindowAcknowledgementSize(window_acknowledgement_size)
-            }
-            MessageTypeId::SetPeerBandwidth => {
-                let (_, set_peer_bandwidth) = RTMPReader::read_set_peer_bandwidth(message.payload.as_slice())
-                    .expect("Failed to parse set peer bandwidth");
-                RTMPMessageType::SetPeerBandwidth(set_peer_bandwidth)
-            }
-            MessageTypeId::UserControlMessage => {
-                let (_, user_control_message) = UserControlMessageReader::read(message.payload.as_slice())
-                    .expect("Failed to parse user control message");
-                RTMPMessageType::UserControlMessage(user_control_message)
+        let (mut i, handler) = amf_decoder
+            .parse_single_element(payload)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse data handler: {:?}", e)))?;
+
+        let handler = match unwrap_amf3((*handler).clone()) {
+            Value::String(s) => s,
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data handler type")),
+        };
+
+        let mut arguments = Vec::new();
+
+        while !i.is_empty() {
+            let (j, argument) = amf_decoder
+                .parse_single_element(i)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse data argument: {:?}", e)))?;
+
+            arguments.push(unwrap_amf3((*argument).clone()));
+
+            i = j;
+        }
+
+        Ok(DataMessage {
+            handler,
+            arguments,
+            object_encoding,
+        })
+    }
+
+    /// Same layout as AMF3 commands, a format byte and AMF0 with AMF3 switches.
+    fn read_amf3_data(payload: &[u8]) -> std::io::Result<DataMessage> {
+        match payload.split_first() {
+            Some((0, body)) => RTMPReader::read_amf0_data(body, ObjectEncoding::AMF3),
+            Some((format, _)) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unsupported AMF3 data format {}", format),
+            )),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Empty AMF3 data message",
+            )),
+        }
+    }
+
+    /// AMF3 commands start with a format byte, followed by the same AMF0
+    /// encoding as AMF0 commands that switches to AMF3 for complex values.
+    fn read_amf3_command(payload: &[u8]) -> std::io::Result<AMFCommandMessage> {
+        match payload.split_first() {
+            Some((0, body)) => RTMPReader::read_amf0_command(body),
+            Some((format, _)) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unsupported AMF3 command format {}", format),
+            )),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Empty AMF3 command",
+            )),
+        }
+    }
+
+    /// Passes a message through untouched, used for types we don't parse.
+    fn read_raw(message: RTMPMessage) -> RTMPMessageType {
+        RTMPMessageType::Raw {
+            type_id: message.message_type_id,
+            stream_id: message.message_stream_id,
+            timestamp: message.timestamp,
+            payload: message.payload,
+        }
+    }
+
+    /// Returns the next message, either one split off an earlier aggregate
+    /// or a freshly dechunked one.
+    fn next_message<T: Transport>(context: &mut NetConnectionContext<T>) -> std::io::Result<RTMPMessage> {
+        loop {
+            let message = match context.pending_messages.pop_front() {
+                Some(message) => message,
+                None => RTMPDechunker::read_chunks(context)?,
+            };
+
+            match MessageTypeId::try_from(message.message_type_id) {
+                Ok(MessageTypeId::AggregateMessage) => {
+                    let sub_messages = AggregateMessageReader::read(&message)?;
+                    context.pending_messages.extend(sub_messages);
+                }
+                _ => return Ok(message),
             }
-            MessageTypeId::SetChunkSize => {
-                let (_, chunk_size) = RTMPReader::read_set_chunk_size(message.payload.as_slice())
-                    .expect("Failed to parse set chunk size");
-                RTMPMessageType::SetChunkSize(chunk_size)
+        }
+    }
+
+    pub fn read<'b, T: Transport>(context: &mut NetConnectionContext<T>) -> std::io::Result<(RTMPMessageHeader, RTMPMessageType)> {
+        let message = RTMPReader::next_message(context)?;
+
+        let header = RTMPMessageHeader {
+            timestamp: message.timestamp,
+            message_stream_id: message.message_stream_id,
+        };
+
+        let message_type_id = match MessageTypeId::try_from(message.message_type_id) {
+            Ok(message_type_id) => message_type_id,
+            Err(_) => return Ok((header, RTMPReader::read_raw(message))),
+        };
+
+        let parsed_message = match message_type_id {
+            // malformed protocol control messages are handed out untouched too
+            MessageTypeId::WindowAcknowledgementSize => {
+                match RTMPReader::read_window_acknowledgement_size(message.payload.as_slice()) {
+                    Ok((_, window_acknowledgement_size)) => {
+                        RTMPMessageType::WindowAcknowledgementSize(window_acknowledgement_size)
+                    }
+                    Err(_) => RTMPReader::read_raw(message),
+                }
             }
+            MessageTypeId::SetPeerBandwidth => match RTMPReader::read_set_peer_bandwidth(message.payload.as_slice()) {
+                Ok((_, set_peer_bandwidth)) => RTMPMessageType::SetPeerBandwidth(set_peer_bandwidth),
+                Err(_) => RTMPReader::read_raw(message),
+            },
+            MessageTypeId::UserControlMessage => match UserControlMessageReader::read(message.payload.as_slice()) {
+                Ok((_, user_control_message)) => RTMPMessageType::UserControlMessage(user_control_message),
+                // event types we don't know, like the buffer events of some servers
+                Err(_) => RTMPReader::read_raw(message),
+            },
+            MessageTypeId::SetChunkSize => match RTMPReader::read_set_chunk_size(message.payload.as_slice()) {
+                Ok((_, chunk_size)) => RTMPMessageType::SetChunkSize(chunk_size),
+                Err(_) => RTMPReader::read_raw(message),
+            },
             MessageTypeId::CommandAMF0 => {
-                let command = RTMPReader::read_amf0_command(message.payload)?;
+                let command = RTMPReader::read_amf0_command(message.payload.as_slice())?;
                 RTMPMessageType::AMF0Command(command)
             }
-            MessageTypeId::SharedObjectAMF0 => {
-                let (_, shared_object) = SharedObjectReader::new().read(context, message.payload.as_slice())
-                    .expect("Failed to parse shared object");
-                RTMPMessageType::AMF3SharedObject(shared_object)
+            MessageTypeId::CommandAMF3 => {
+                let command = RTMPReader::read_amf3_command(message.payload.as_slice())?;
+                RTMPMessageType::AMF3Command(command)
+            }
+            MessageTypeId::DataAMF0 => {
+                let data = RTMPReader::read_amf0_data(message.payload.as_slice(), ObjectEncoding::AMF0)?;
+                RTMPMessageType::Data(data)
+            }
+            MessageTypeId::DataAMF3 => {
+                let data = RTMPReader::read_amf3_data(message.payload.as_slice())?;
+                RTMPMessageType::Data(data)
             }
-            _ => todo!("Parsing Message type {:?} not implemented", message.message_type_id),
+            MessageTypeId::AudioData => match MediaReader::read_audio(message.payload.as_slice()) {
+                Ok((_, audio)) => RTMPMessageType::Audio(audio),
+                // empty or unknown codec data is still handed out untouched
+                Err(_) => RTMPReader::read_raw(message),
+            },
+            MessageTypeId::VideoData => match MediaReader::read_video(message.payload.as_slice()) {
+                Ok((_, video)) => RTMPMessageType::Video(video),
+                Err(_) => RTMPReader::read_raw(message),
+            },
+            // also for shared objects we don't use
+            MessageTypeId::SharedObjectAMF0 => match SharedObjectReader::new().read(context, message.payload.as_slice()) {
+                Ok((_, shared_object)) => RTMPMessageType::AMF3SharedObject(shared_object),
+                Err(_) => RTMPReader::read_raw(message),
+            },
+            _ => RTMPReader::read_raw(message),
         };
 
-        Ok(parsed_message)
+        Ok((header, parsed_message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::allocate_net_connection_context;
+    use crate::net_connection::writer::RTMPWriter;
+    use crate::transport::memory_transport::MemoryTransport;
+
+    #[test]
+    fn test_malformed_control_messages() {
+        let (client, server) = MemoryTransport::pair();
+        let mut client = allocate_net_connection_context(client);
+        let mut server = allocate_net_connection_context(server);
+
+        // too short for their payload, or a shared object nobody uses
+        for type_id in [1u8, 5, 6, 19] {
+            let message = RTMPMessageType::Raw { type_id, stream_id: 0, timestamp: 0, payload: vec![0, 1] };
+            RTMPWriter::write(message, &mut server).unwrap();
+
+            match RTMPReader::read(&mut client).unwrap() {
+                (_, RTMPMessageType::Raw { type_id: read_type_id, .. }) => assert_eq!(read_type_id, type_id),
+                (_, message) => panic!("unexpected message {:?}", message),
+            }
+        }
     }
 }
@@ -0,0 +1,60 @@
+/// Where a `NetConnection` is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionState {
+    /// connect was never called
+    #[default]
+    Disconnected,
+    /// The transport is open and the handshake is under way
+    Handshaking,
+    /// Waiting for the answer to connect
+    Connecting,
+    Connected,
+    /// close was called, streams and shared objects are being released
+    Closing,
+    /// The transport is gone, connect or reconnect start over
+    Closed,
+}
+
+impl ConnectionState {
+    pub fn can_transition_to(&self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+
+        matches!(
+            (self, next),
+            (Disconnected | Closed, Handshaking)
+                // redirects, authentication rounds and reconnects open a new transport
+                | (Connecting | Connected, Handshaking)
+                | (Handshaking, Connecting)
+                | (Connecting, Connected)
+                | (Connected, Closing)
+                | (Handshaking | Connecting | Connected | Closing, Closed)
+        )
+    }
+
+    /// Whether messages can be read and sent.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::Handshaking | ConnectionState::Connecting | ConnectionState::Connected | ConnectionState::Closing
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transitions() {
+        use ConnectionState::*;
+
+        assert!(Disconnected.can_transition_to(Handshaking));
+        assert!(Connected.can_transition_to(Closing));
+        assert!(Closing.can_transition_to(Closed));
+        assert!(Closed.can_transition_to(Handshaking));
+
+        assert!(!Disconnected.can_transition_to(Connected));
+        assert!(!Closed.can_transition_to(Closing));
+        assert!(!Closing.can_transition_to(Connected));
+    }
+}
@@ -0,0 +1,293 @@
+use std::rc::Rc;
+
+use flash_lso::types::{Element, Value};
+
+use crate::utils::amf::{get_properties, get_string};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetStatusLevel {
+    Status,
+    Warning,
+    Error,
+}
+
+impl NetStatusLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetStatusLevel::Status => "status",
+            NetStatusLevel::Warning => "warning",
+            NetStatusLevel::Error => "error",
+        }
+    }
+}
+
+impl From<&str> for NetStatusLevel {
+    fn from(value: &str) -> Self {
+        match value {
+            "error" => NetStatusLevel::Error,
+            "warning" => NetStatusLevel::Warning,
+            _ => NetStatusLevel::Status,
+        }
+    }
+}
+
+/// Well known status codes, anything else is kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetStatusCode {
+    CallBadVersion,
+    CallFailed,
+    CallProhibited,
+    ConnectAppShutdown,
+    ConnectClosed,
+    ConnectFailed,
+    ConnectIdleTimeout,
+    ConnectInvalidApp,
+    ConnectNetworkChange,
+    ConnectReconnectRequest,
+    ConnectRejected,
+    ConnectSuccess,
+
+    StreamFailed,
+    StreamBufferEmpty,
+    StreamBufferFull,
+    StreamBufferFlush,
+    StreamPublishStart,
+    StreamPublishBadName,
+    StreamPublishIdle,
+    StreamUnpublishSuccess,
+    StreamPlayStart,
+    StreamPlayStop,
+    StreamPlayFailed,
+    StreamPlayStreamNotFound,
+    StreamPlayReset,
+    StreamPlayPublishNotify,
+    StreamPlayUnpublishNotify,
+    StreamPlayInsufficientBW,
+    StreamPlayTransition,
+    StreamPlayComplete,
+    StreamPauseNotify,
+    StreamUnpauseNotify,
+    StreamSeekNotify,
+    StreamSeekFailed,
+    StreamSeekInvalidTime,
+    StreamRecordStart,
+    StreamRecordStop,
+    StreamRecordFailed,
+    StreamRecordNoAccess,
+
+    SharedObjectFlushSuccess,
+    SharedObjectFlushFailed,
+    SharedObjectBadPersistence,
+    SharedObjectUriMismatch,
+
+    Other(String),
+}
+
+impl NetStatusCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            NetStatusCode::CallBadVersion => "NetConnection.Call.BadVersion",
+            NetStatusCode::CallFailed => "NetConnection.Call.Failed",
+            NetStatusCode::CallProhibited => "NetConnection.Call.Prohibited",
+            NetStatusCode::ConnectAppShutdown => "NetConnection.Connect.AppShutdown",
+            NetStatusCode::ConnectClosed => "NetConnection.Connect.Closed",
+            NetStatusCode::ConnectFailed => "NetConnection.Connect.Failed",
+            NetStatusCode::ConnectIdleTimeout => "NetConnection.Connect.IdleTimeOut",
+            NetStatusCode::ConnectInvalidApp => "NetConnection.Connect.InvalidApp",
+            NetStatusCode::ConnectNetworkChange => "NetConnection.Connect.NetworkChange",
+            NetStatusCode::ConnectReconnectRequest => "NetConnection.Connect.ReconnectRequest",
+            NetStatusCode::ConnectRejected => "NetConnection.Connect.Rejected",
+            NetStatusCode::ConnectSuccess => "NetConnection.Connect.Success",
+
+            NetStatusCode::StreamFailed => "NetStream.Failed",
+            NetStatusCode::StreamBufferEmpty => "NetStream.Buffer.Empty",
+            NetStatusCode::StreamBufferFull => "NetStream.Buffer.Full",
+            NetStatusCode::StreamBufferFlush => "NetStream.Buffer.Flush",
+            NetStatusCode::StreamPublishStart => "NetStream.Publish.Start",
+            NetStatusCode::StreamPublishBadName => "NetStream.Publish.BadName",
+            NetStatusCode::StreamPublishIdle => "NetStream.Publish.Idle",
+            NetStatusCode::StreamUnpublishSuccess => "NetStream.Unpublish.Success",
+            NetStatusCode::StreamPlayStart => "NetStream.Play.Start",
+            NetStatusCode::StreamPlayStop => "NetStream.Play.Stop",
+            NetStatusCode::StreamPlayFailed => "NetStream.Play.Failed",
+            NetStatusCode::StreamPlayStreamNotFound => "NetStream.Play.StreamNotFound",
+            NetStatusCode::StreamPlayReset => "NetStream.Play.Reset",
+            NetStatusCode::StreamPlayPublishNotify => "NetStream.Play.PublishNotify",
+            NetStatusCode::StreamPlayUnpublishNotify => "NetStream.Play.UnpublishNotify",
+            NetStatusCode::StreamPlayInsufficientBW => "NetStream.Play.InsufficientBW",
+            NetStatusCode::StreamPlayTransition => "NetStream.Play.Transition",
+            NetStatusCode::StreamPlayComplete => "NetStream.Play.Complete",
+            NetStatusCode::StreamPauseNotify => "NetStream.Pause.Notify",
+            NetStatusCode::StreamUnpauseNotify => "NetStream.Unpause.Notify",
+            NetStatusCode::StreamSeekNotify => "NetStream.Seek.Notify",
+            NetStatusCode::StreamSeekFailed => "NetStream.Seek.Failed",
+            NetStatusCode::StreamSeekInvalidTime => "NetStream.Seek.InvalidTime",
+            NetStatusCode::StreamRecordStart => "NetStream.Record.Start",
+            NetStatusCode::StreamRecordStop => "NetStream.Record.Stop",
+            NetStatusCode::StreamRecordFailed => "NetStream.Record.Failed",
+            NetStatusCode::StreamRecordNoAccess => "NetStream.Record.NoAccess",
+
+            NetStatusCode::SharedObjectFlushSuccess => "SharedObject.Flush.Success",
+            NetStatusCode::SharedObjectFlushFailed => "SharedObject.Flush.Failed",
+            NetStatusCode::SharedObjectBadPersistence => "SharedObject.BadPersistence",
+            NetStatusCode::SharedObjectUriMismatch => "SharedObject.UriMismatch",
+
+            NetStatusCode::Other(code) => code,
+        }
+    }
+}
+
+impl From<&str> for NetStatusCode {
+    fn from(value: &str) -> Self {
+        match value {
+            "NetConnection.Call.BadVersion" => NetStatusCode::CallBadVersion,
+            "NetConnection.Call.Failed" => NetStatusCode::CallFailed,
+            "NetConnection.Call.Prohibited" => NetStatusCode::CallProhibited,
+            "NetConnection.Connect.AppShutdown" => NetStatusCode::ConnectAppShutdown,
+            "NetConnection.Connect.Closed" => NetStatusCode::ConnectClosed,
+            "NetConnection.Connect.Failed" => NetStatusCode::ConnectFailed,
+            "NetConnection.Connect.IdleTimeOut" => NetStatusCode::ConnectIdleTimeout,
+            "NetConnection.Connect.InvalidApp" => NetStatusCode::ConnectInvalidApp,
+            "NetConnection.Connect.NetworkChange" => NetStatusCode::ConnectNetworkChange,
+            "NetConnection.Connect.ReconnectRequest" => NetStatusCode::ConnectReconnectRequest,
+            "NetConnection.Connect.Rejected" => NetStatusCode::ConnectRejected,
+            "NetConnection.Connect.Success" => NetStatusCode::ConnectSuccess,
+
+            "NetStream.Failed" => NetStatusCode::StreamFailed,
+            "NetStream.Buffer.Empty" => NetStatusCode::StreamBufferEmpty,
+            "NetStream.Buffer.Full" => NetStatusCode::StreamBufferFull,
+            "NetStream.Buffer.Flush" => NetStatusCode::StreamBufferFlush,
+            "NetStream.Publish.Start" => NetStatusCode::StreamPublishStart,
+            "NetStream.Publish.BadName" => NetStatusCode::StreamPublishBadName,
+            "NetStream.Publish.Idle" => NetStatusCode::StreamPublishIdle,
+            "NetStream.Unpublish.Success" => NetStatusCode::StreamUnpublishSuccess,
+            "NetStream.Play.Start" => NetStatusCode::StreamPlayStart,
+            "NetStream.Play.Stop" => NetStatusCode::StreamPlayStop,
+            "NetStream.Play.Failed" => NetStatusCode::StreamPlayFailed,
+            "NetStream.Play.StreamNotFound" => NetStatusCode::StreamPlayStreamNotFound,
+            "NetStream.Play.Reset" => NetStatusCode::StreamPlayReset,
+            "NetStream.Play.PublishNotify" => NetStatusCode::StreamPlayPublishNotify,
+            "NetStream.Play.UnpublishNotify" => NetStatusCode::StreamPlayUnpublishNotify,
+            "NetStream.Play.InsufficientBW" => NetStatusCode::StreamPlayInsufficientBW,
+            "NetStream.Play.Transition" => NetStatusCode::StreamPlayTransition,
+            "NetStream.Play.Complete" => NetStatusCode::StreamPlayComplete,
+            "NetStream.Pause.Notify" => NetStatusCode::StreamPauseNotify,
+            "NetStream.Unpause.Notify" => NetStatusCode::StreamUnpauseNotify,
+            "NetStream.Seek.Notify" => NetStatusCode::StreamSeekNotify,
+            "NetStream.Seek.Failed" => NetStatusCode::StreamSeekFailed,
+            "NetStream.Seek.InvalidTime" => NetStatusCode::StreamSeekInvalidTime,
+            "NetStream.Record.Start" => NetStatusCode::StreamRecordStart,
+            "NetStream.Record.Stop" => NetStatusCode::StreamRecordStop,
+            "NetStream.Record.Failed" => NetStatusCode::StreamRecordFailed,
+            "NetStream.Record.NoAccess" => NetStatusCode::StreamRecordNoAccess,
+
+            "SharedObject.Flush.Success" => NetStatusCode::SharedObjectFlushSuccess,
+            "SharedObject.Flush.Failed" => NetStatusCode::SharedObjectFlushFailed,
+            "SharedObject.BadPersistence" => NetStatusCode::SharedObjectBadPersistence,
+            "SharedObject.UriMismatch" => NetStatusCode::SharedObjectUriMismatch,
+
+            code => NetStatusCode::Other(code.to_string()),
+        }
+    }
+}
+
+/// The info object of onStatus calls and of most `_result`/`_error` responses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetStatus {
+    pub code: NetStatusCode,
+    pub level: NetStatusLevel,
+    pub description: Option<String>,
+    /// Every other property, e.g. `objectEncoding`, `ex` or `application`
+    pub extra: Vec<Element>,
+}
+
+impl NetStatus {
+    pub fn new(code: NetStatusCode, level: NetStatusLevel, description: &str) -> Self {
+        NetStatus {
+            code,
+            level,
+            description: Some(description.to_string()),
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn error(code: NetStatusCode, description: &str) -> Self {
+        NetStatus::new(code, NetStatusLevel::Error, description)
+    }
+
+    /// Parses an info object, `None` when it has no code.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let code = get_string(value, "code")?;
+
+        Some(NetStatus {
+            code: NetStatusCode::from(code),
+            level: NetStatusLevel::from(get_string(value, "level").unwrap_or_default()),
+            description: get_string(value, "description").map(str::to_string),
+            extra: get_properties(value)
+                .iter()
+                .filter(|element| !matches!(element.name.as_str(), "code" | "level" | "description"))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Finds the info object in the arguments of a response or call.
+    pub fn from_arguments(arguments: &[Value]) -> Option<Self> {
+        arguments.iter().find_map(NetStatus::from_value)
+    }
+
+    pub fn get_extra(&self, name: &str) -> Option<&Value> {
+        self.extra
+            .iter()
+            .find(|element| element.name == name)
+            .map(|element| element.value.as_ref())
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == NetStatusLevel::Error
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut elements = vec![
+            Element {
+                name: String::from("level"),
+                value: Rc::new(Value::String(self.level.as_str().to_string())),
+            },
+            Element {
+                name: String::from("code"),
+                value: Rc::new(Value::String(self.code.as_str().to_string())),
+            },
+        ];
+
+        if let Some(description) = &self.description {
+            elements.push(Element {
+                name: String::from("description"),
+                value: Rc::new(Value::String(description.clone())),
+            });
+        }
+
+        elements.extend(self.extra.iter().cloned());
+
+        Value::Object(elements, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut status = NetStatus::error(NetStatusCode::ConnectRejected, "[ AccessManager.Reject ]");
+        status.extra.push(Element {
+            name: String::from("ex"),
+            value: Rc::new(Value::Object(vec![], None)),
+        });
+
+        let parsed = NetStatus::from_arguments(&[Value::Null, status.to_value()]).unwrap();
+        assert_eq!(parsed, status);
+        assert!(parsed.get_extra("ex").is_some());
+
+        assert_eq!(NetStatusCode::from("Custom.Code"), NetStatusCode::Other(String::from("Custom.Code")));
+    }
+}
@@ -1,21 +1,82 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use flash_lso::types::Value;
 
+use crate::errors::ProtocolError;
 use crate::net_connection::packets::AMFCommandMessage;
+use crate::net_connection::status::{NetStatus, NetStatusCode};
+
+/// Calls still waiting for an answer, beyond which new calls are refused.
+pub const DEFAULT_MAX_PENDING_TRANSACTIONS: usize = 1024;
+
+pub type ResponderCallback = Box<dyn Fn(Value, &[Value])>;
+
+/// Callbacks for the answer to a call, like AS3's `Responder`. Both get the
+/// command object and the arguments of the `_result` or `_error` response.
+pub struct Responder {
+    result_callback: ResponderCallback,
+    error_callback: Option<ResponderCallback>,
+}
+
+impl Responder {
+    /// Only for calls whose failure can be ignored, errors, timeouts and
+    /// dropped connections go unnoticed. Use `with_error` otherwise.
+    pub fn new<F: Fn(Value, &[Value]) + 'static>(result_callback: F) -> Self {
+        Responder {
+            result_callback: Box::new(result_callback),
+            error_callback: None,
+        }
+    }
+
+    pub fn with_error<F, E>(result_callback: F, error_callback: E) -> Self
+    where
+        F: Fn(Value, &[Value]) + 'static,
+        E: Fn(Value, &[Value]) + 'static,
+    {
+        Responder {
+            result_callback: Box::new(result_callback),
+            error_callback: Some(Box::new(error_callback)),
+        }
+    }
+
+    /// Runs the callback matching the outcome of a call.
+    pub fn respond(&self, result: TransactionResult, command_object: Value, arguments: &[Value]) {
+        match result {
+            TransactionResult::Result => (self.result_callback)(command_object, arguments),
+            TransactionResult::Error => {
+                if let Some(error_callback) = &self.error_callback {
+                    error_callback(command_object, arguments);
+                }
+            }
+        }
+    }
+
+    /// Runs the error path with a locally made status, as if the server
+    /// had answered with `_error`.
+    fn fail(&self, status: NetStatus) {
+        if let Some(error_callback) = &self.error_callback {
+            error_callback(Value::Null, &[status.to_value()]);
+        }
+    }
+}
 
 pub struct Transaction {
-    result_callback: Box<dyn Fn(Value, &[Value])>,
+    responder: Responder,
+    /// Past this point the call fails with a timeout
+    deadline: Option<Instant>,
 }
 
 impl std::fmt::Debug for Transaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Transaction")
             .field("result_callback", &"Fn(Value, &[Value])")
+            .field("error_callback", &self.responder.error_callback.is_some())
+            .field("deadline", &self.deadline)
             .finish()
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransactionResult {
     Result,
     Error,
@@ -23,53 +84,131 @@ pub enum TransactionResult {
 
 #[derive(Debug)]
 pub struct TransactionManager {
-    current_transaction_id: Arc<Mutex<u32>>,
+    next_transaction_id: u32,
     transactions: HashMap<u32, Transaction>,
+
+    /// Timeout of calls that don't set their own, `None` waits forever
+    pub default_timeout: Option<Duration>,
+    pub max_pending_transactions: usize,
+}
+
+impl Default for TransactionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TransactionManager {
     pub fn new() -> Self {
         TransactionManager {
-            current_transaction_id: Arc::new(Mutex::new(1)),
+            next_transaction_id: 2,
             transactions: HashMap::new(),
+            default_timeout: None,
+            max_pending_transactions: DEFAULT_MAX_PENDING_TRANSACTIONS,
+        }
+    }
+
+    /// Next free id, wrapping around and skipping 0, which means no
+    /// answer is expected, as well as ids still waiting for one.
+    fn allocate_transaction_id(&mut self) -> u32 {
+        loop {
+            let transaction_id = self.next_transaction_id;
+            self.next_transaction_id = self.next_transaction_id.wrapping_add(1);
+
+            if transaction_id != 0 && !self.transactions.contains_key(&transaction_id) {
+                return transaction_id;
+            }
         }
     }
 
-    pub fn initialize_transaction(
+    pub fn initialize_transaction(&mut self, responder: Responder) -> std::io::Result<u32> {
+        let timeout = self.default_timeout;
+        self.initialize_transaction_with_timeout(responder, timeout)
+    }
+
+    pub fn initialize_transaction_with_timeout(
         &mut self,
-        result_callback: Box<dyn Fn(Value, &[Value])>,
-    ) -> u32 {
-        let mut current_transaction_id = self.current_transaction_id.lock().unwrap();
-        *current_transaction_id += 1;
+        responder: Responder,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<u32> {
+        if self.transactions.len() >= self.max_pending_transactions {
+            return Err(ProtocolError::TooManyPendingTransactions {
+                limit: self.max_pending_transactions,
+            }
+            .into());
+        }
 
-        let transaction_id = *current_transaction_id;
+        let transaction_id = self.allocate_transaction_id();
         self.transactions.insert(
             transaction_id,
             Transaction {
-                result_callback,
+                responder,
+                deadline: timeout.map(|timeout| Instant::now() + timeout),
             },
         );
 
-        transaction_id
+        Ok(transaction_id)
     }
 
     pub fn finalize_transaction(&mut self, transaction_id: u32, result: TransactionResult, response: AMFCommandMessage) -> std::io::Result<()> {
-        let transaction = self.get_transaction(transaction_id).unwrap();
-        let response = response;
-
-        let callback = match result {
-            TransactionResult::Result => &transaction.result_callback,
-            // TransactionResult::Error => &transaction.error_callback,
-            TransactionResult::Error => todo!("Error callback"),
+        // answers to calls we never made, or already gave up on, are dropped
+        let transaction = match self.transactions.remove(&transaction_id) {
+            Some(transaction) => transaction,
+            None => return Ok(()),
         };
 
-        callback(response.command_object.unwrap(), &response.optional_arguments);
-
-        self.clean_up_transaction(transaction_id);
+        // many responses, like the one to createStream, come without command object
+        transaction.responder.respond(
+            result,
+            response.command_object.unwrap_or(Value::Null),
+            &response.optional_arguments,
+        );
 
         Ok(())
     }
 
+    /// Forgets a call without running any of its callbacks. Returns false if
+    /// it had already been answered.
+    pub fn cancel_transaction(&mut self, transaction_id: u32) -> bool {
+        self.transactions.remove(&transaction_id).is_some()
+    }
+
+    /// Earliest deadline of the pending calls.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.transactions.values().filter_map(|transaction| transaction.deadline).min()
+    }
+
+    /// Fails every call whose deadline has passed.
+    pub fn check_timeouts(&mut self, now: Instant) {
+        let expired: Vec<u32> = self
+            .transactions
+            .iter()
+            .filter(|(_, transaction)| transaction.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect();
+
+        for transaction_id in expired {
+            if let Some(transaction) = self.transactions.remove(&transaction_id) {
+                transaction
+                    .responder
+                    .fail(NetStatus::error(NetStatusCode::CallFailed, "Call timed out"));
+            }
+        }
+    }
+
+    /// Fails every pending call, used when the connection is gone.
+    pub fn fail_all(&mut self, description: &str) {
+        for (_, transaction) in self.transactions.drain() {
+            transaction
+                .responder
+                .fail(NetStatus::error(NetStatusCode::CallFailed, description));
+        }
+    }
+
+    pub fn pending_transactions(&self) -> usize {
+        self.transactions.len()
+    }
+
     pub fn get_transaction(&self, transaction_id: u32) -> Option<&Transaction> {
         self.transactions.get(&transaction_id)
     }
@@ -78,4 +217,60 @@ impl TransactionManager {
         self.transactions.remove(&transaction_id);
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_timeout_fires_error_path() {
+        let mut transaction_manager = TransactionManager::new();
+        let failures = Rc::new(RefCell::new(Vec::new()));
+
+        let recorded_failures = failures.clone();
+        let transaction_id = transaction_manager
+            .initialize_transaction_with_timeout(
+                Responder::with_error(
+                    |_, _: &[Value]| panic!("no result expected"),
+                    move |_, information: &[Value]| {
+                        recorded_failures.borrow_mut().push(NetStatus::from_arguments(information).unwrap());
+                    },
+                ),
+                Some(Duration::from_secs(5)),
+            )
+            .unwrap();
+
+        transaction_manager.check_timeouts(Instant::now());
+        assert!(failures.borrow().is_empty());
+
+        transaction_manager.check_timeouts(Instant::now() + Duration::from_secs(6));
+        assert_eq!(failures.borrow().len(), 1);
+        assert_eq!(failures.borrow()[0].code, NetStatusCode::CallFailed);
+        assert!(transaction_manager.get_transaction(transaction_id).is_none());
+    }
+
+    #[test]
+    fn test_id_wraparound_skips_pending() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.next_transaction_id = u32::MAX;
+
+        let last = transaction_manager.initialize_transaction(Responder::new(|_, _| {})).unwrap();
+        assert_eq!(last, u32::MAX);
+
+        // 0 is reserved for calls without answer
+        let first = transaction_manager.initialize_transaction(Responder::new(|_, _| {})).unwrap();
+        assert_eq!(first, 1);
+
+        transaction_manager.next_transaction_id = u32::MAX;
+        let next = transaction_manager.initialize_transaction(Responder::new(|_, _| {})).unwrap();
+        assert_eq!(next, 2);
+
+        transaction_manager.max_pending_transactions = 3;
+        assert!(transaction_manager.initialize_transaction(Responder::new(|_, _| {})).is_err());
+        assert!(transaction_manager.cancel_transaction(next));
+        assert!(transaction_manager.initialize_transaction(Responder::new(|_, _| {})).is_ok());
+    }
+}
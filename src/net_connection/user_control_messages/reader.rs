@@ -1,5 +1,8 @@
+use nom::error::ErrorKind;
 use nom::number::complete::{be_u16, be_u32};
 
+use crate::errors::Error;
+
 use crate::net_connection::packets::UserControlMessage;
 use crate::utils::nom::RTMPResult;
 
@@ -76,7 +79,7 @@ impl UserControlMessageReader {
             4 => UserControlMessageReader::read_stream_is_recorded(i)?,
             6 => UserControlMessageReader::read_ping_request(i)?,
             7 => UserControlMessageReader::read_ping_response(i)?,
-            _ => unimplemented!("User control message type not implemented, {:?}", event_type),
+            _ => return Err(nom::Err::Error(Error::Nom(payload, ErrorKind::Switch))),
         };
 
         Ok((i, user_control_message))
@@ -1,11 +1,14 @@
 use crate::{
     chunk::{
-        packets::{ChunkImportance, MessageTypeId},
-        writer::RTMPChunker,
+        packets::MessageTypeId,
+        scheduler::MessagePriority,
     },
-    context::NetConnectionContext,
+    context::{NetConnectionContext, ObjectEncoding, MAX_CHUNK_SIZE},
+    media::writer::MediaWriter,
+    net_connection::aggregate_messages::writer::AggregateMessageWriter,
     net_connection::packets::{
-        AMFCommandMessage, RTMPMessage, RTMPMessageType, UserControlMessage,
+        AMFCommandMessage, DataMessage, RTMPMessage, RTMPMessageHeader, RTMPMessageType, SetChunkSize,
+        UserControlMessage,
     },
     shared_object::writer::SharedObjectWriter,
     transport::Transport,
@@ -32,9 +35,9 @@ impl RTMPWriter {
             &Rc::new(Value::Number(command.transaction_id as f64)),
         )?;
 
-        if let Some(command_object) = command.command_object {
-            write_value(payload_vector, &Rc::new(command_object))?;
-        }
+        // the command object is mandatory on the wire, null when there is none
+        let command_object = command.command_object.unwrap_or(Value::Null);
+        write_value(payload_vector, &Rc::new(command_object))?;
 
         for optional_argument in command.optional_arguments {
             write_value(payload_vector, &Rc::new(optional_argument))?;
@@ -43,11 +46,69 @@ impl RTMPWriter {
         Ok(())
     }
 
+    /// Writes the format byte, then the command in AMF0 with every complex
+    /// value switched over to AMF3.
+    fn write_amf3_command(
+        command: AMFCommandMessage,
+        payload_vector: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        payload_vector.push(0x00);
+
+        let switch_to_amf3 = |value: Value| match value {
+            Value::Null | Value::Undefined | Value::AMF3(_) => value,
+            _ => Value::AMF3(Rc::new(value)),
+        };
+
+        RTMPWriter::write_amf0_command(
+            AMFCommandMessage {
+                procedure_name: command.procedure_name,
+                transaction_id: command.transaction_id,
+                command_object: command.command_object.map(switch_to_amf3),
+                optional_arguments: command
+                    .optional_arguments
+                    .into_iter()
+                    .map(switch_to_amf3)
+                    .collect(),
+            },
+            payload_vector,
+        )
+    }
+
+    pub(crate) fn write_data(
+        data: DataMessage,
+        payload_vector: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        let object_encoding = data.object_encoding;
+
+        if object_encoding == ObjectEncoding::AMF3 {
+            payload_vector.push(0x00);
+        }
+
+        write_value(payload_vector, &Rc::new(Value::String(data.handler)))?;
+
+        for argument in data.arguments {
+            let argument = match argument {
+                Value::Null | Value::Undefined | Value::AMF3(_) => argument,
+                _ if object_encoding == ObjectEncoding::AMF3 => Value::AMF3(Rc::new(argument)),
+                _ => argument,
+            };
+
+            write_value(payload_vector, &Rc::new(argument))?;
+        }
+
+        Ok(())
+    }
+
     fn write_user_control_message(
         user_control_message: UserControlMessage,
         payload_vector: &mut Vec<u8>,
     ) -> std::io::Result<()> {
         let (event_type, payload): (i16, Vec<u8>) = match user_control_message {
+            UserControlMessage::SetBufferLength { stream_id, buffer_length } => {
+                let mut payload = stream_id.to_be_bytes().to_vec();
+                payload.extend_from_slice(&buffer_length.to_be_bytes());
+                (0x03, payload)
+            }
             UserControlMessage::PingResponse { timestamp } => {
                 (0x07, timestamp.to_be_bytes().to_vec())
             }
@@ -63,48 +124,180 @@ impl RTMPWriter {
         Ok(())
     }
 
-    pub fn write<T: Transport>(
+    fn write_set_chunk_size(
+        set_chunk_size: SetChunkSize,
+        payload_vector: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        payload_vector.extend_from_slice(&(set_chunk_size.size & MAX_CHUNK_SIZE).to_be_bytes());
+
+        Ok(())
+    }
+
+    fn priority(payload: &RTMPMessageType) -> MessagePriority {
+        match payload {
+            RTMPMessageType::SetChunkSize(_)
+            | RTMPMessageType::UserControlMessage(_)
+            | RTMPMessageType::WindowAcknowledgementSize(_)
+            | RTMPMessageType::SetPeerBandwidth(_) => MessagePriority::Control,
+            RTMPMessageType::Audio(_) => MessagePriority::Audio,
+            RTMPMessageType::Video(_) => MessagePriority::Video,
+            RTMPMessageType::Raw { type_id, .. } => MessagePriority::from_type_id(*type_id),
+            _ => MessagePriority::Command,
+        }
+    }
+
+    /// Serializes a message and queues it in the outgoing scheduler without
+    /// sending anything yet.
+    pub fn enqueue<T: Transport>(
+        payload: RTMPMessageType,
+        context: &mut NetConnectionContext<T>,
+    ) -> std::io::Result<()> {
+        RTMPWriter::enqueue_with_header(payload, RTMPMessageHeader::default(), context)
+    }
+
+    /// Same as `enqueue`, with the timestamp and message stream given by the caller.
+    pub fn enqueue_with_header<T: Transport>(
         payload: RTMPMessageType,
+        header: RTMPMessageHeader,
         context: &mut NetConnectionContext<T>,
     ) -> std::io::Result<()> {
         let mut payload_vector: Vec<u8> = Vec::new();
+        let mut header = header;
 
-        let (message_type_id, chunk_stream_id) = match payload {
+        let message_type_id = match payload {
             RTMPMessageType::AMF0Command(command) => {
                 RTMPWriter::write_amf0_command(command, &mut payload_vector)?;
 
-                (MessageTypeId::CommandAMF0, ChunkImportance::CommandAMF0AMF3)
+                MessageTypeId::CommandAMF0 as u8
+            }
+            RTMPMessageType::AMF3Command(command) => {
+                RTMPWriter::write_amf3_command(command, &mut payload_vector)?;
+
+                MessageTypeId::CommandAMF3 as u8
+            }
+            RTMPMessageType::Data(data) => {
+                let message_type_id = match data.object_encoding {
+                    ObjectEncoding::AMF0 => MessageTypeId::DataAMF0,
+                    ObjectEncoding::AMF3 => MessageTypeId::DataAMF3,
+                };
+
+                RTMPWriter::write_data(data, &mut payload_vector)?;
+
+                message_type_id as u8
+            }
+            RTMPMessageType::Audio(audio) => {
+                MediaWriter::write_audio(&audio, &mut payload_vector);
+
+                MessageTypeId::AudioData as u8
+            }
+            RTMPMessageType::Video(video) => {
+                MediaWriter::write_video(&video, &mut payload_vector);
+
+                MessageTypeId::VideoData as u8
+            }
+            RTMPMessageType::SetChunkSize(set_chunk_size) => {
+                RTMPWriter::write_set_chunk_size(set_chunk_size, &mut payload_vector)?;
+
+                MessageTypeId::SetChunkSize as u8
             }
             RTMPMessageType::UserControlMessage(user_control_message) => {
                 RTMPWriter::write_user_control_message(user_control_message, &mut payload_vector)?;
 
-                (
-                    MessageTypeId::UserControlMessage,
-                    ChunkImportance::ProtocolUserControl,
-                )
+                MessageTypeId::UserControlMessage as u8
             }
             RTMPMessageType::AMF3SharedObject(shared_object) => {
                 SharedObjectWriter::new(shared_object).write(&mut payload_vector, context)?;
 
-                (
-                    MessageTypeId::SharedObjectAMF3,
-                    ChunkImportance::CommandAMF0AMF3,
-                )
+                MessageTypeId::SharedObjectAMF3 as u8
+            }
+            RTMPMessageType::Raw { type_id, stream_id, timestamp, payload } => {
+                payload_vector = payload;
+                header = RTMPMessageHeader {
+                    timestamp,
+                    message_stream_id: stream_id,
+                };
+
+                type_id
             }
             _ => {
                 todo!("Payload type not implemented")
             }
         };
 
+        let message_stream_id = header.message_stream_id;
+        let chunk_stream_id = context
+            .outgoing
+            .allocator
+            .chunk_stream_id(message_stream_id, MessagePriority::from_type_id(message_type_id))?;
+
         let rtmp_message = RTMPMessage {
-            timestamp: 0,
+            timestamp: header.timestamp,
             message_type_id,
             chunk_stream_id,
-            message_stream_id: 0,
+            message_stream_id,
             payload: payload_vector,
         };
 
-        RTMPChunker::write_chunks(rtmp_message, context)?;
+        context.outgoing.enqueue(rtmp_message);
+
+        Ok(())
+    }
+
+    /// Sends everything queued so far, interleaved by priority.
+    pub fn flush<T: Transport>(context: &mut NetConnectionContext<T>) -> std::io::Result<()> {
+        context
+            .outgoing
+            .flush(&mut context.transport, &mut context.out_chunk_size)
+    }
+
+    /// Sends queued chunks while they have at least `priority`, at most `max_chunks`.
+    pub fn flush_chunks<T: Transport>(
+        context: &mut NetConnectionContext<T>,
+        priority: MessagePriority,
+        max_chunks: usize,
+    ) -> std::io::Result<()> {
+        context
+            .outgoing
+            .flush_chunks(&mut context.transport, &mut context.out_chunk_size, priority, max_chunks)
+    }
+
+    /// Sends a batch of audio, video and data messages as one aggregate.
+    pub fn write_aggregate<T: Transport>(
+        messages: &[RTMPMessage],
+        context: &mut NetConnectionContext<T>,
+    ) -> std::io::Result<()> {
+        let mut aggregate = AggregateMessageWriter::write(messages)?;
+
+        aggregate.chunk_stream_id = context
+            .outgoing
+            .allocator
+            .chunk_stream_id(aggregate.message_stream_id, MessagePriority::from_type_id(aggregate.message_type_id))?;
+
+        context.outgoing.enqueue(aggregate);
+        RTMPWriter::flush(context)?;
+
+        Ok(())
+    }
+
+    pub fn write<T: Transport>(
+        payload: RTMPMessageType,
+        context: &mut NetConnectionContext<T>,
+    ) -> std::io::Result<()> {
+        RTMPWriter::write_with_header(payload, RTMPMessageHeader::default(), context)
+    }
+
+    /// Sends a message along with everything queued ahead of it. Queued
+    /// messages of a lower priority, like a keyframe behind a command, stay
+    /// queued.
+    pub fn write_with_header<T: Transport>(
+        payload: RTMPMessageType,
+        header: RTMPMessageHeader,
+        context: &mut NetConnectionContext<T>,
+    ) -> std::io::Result<()> {
+        let priority = RTMPWriter::priority(&payload);
+
+        RTMPWriter::enqueue_with_header(payload, header, context)?;
+        RTMPWriter::flush_chunks(context, priority, usize::MAX)?;
 
         Ok(())
     }
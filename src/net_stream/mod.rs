@@ -0,0 +1,568 @@
+use flash_lso::types::Value;
+use std::sync::{Arc, Mutex};
+
+use crate::media::packets::{AudioMessage, VideoMessage};
+use crate::net_connection::events::MAX_QUEUED_EVENTS;
+use crate::net_connection::packets::{AMFCommandMessage, DataMessage, RTMPMessageHeader, RTMPMessageType};
+use crate::net_connection::status::{NetStatus, NetStatusCode};
+use crate::net_connection::transaction_manager::Responder;
+use crate::net_connection::NetConnection;
+use crate::transport::Transport;
+use crate::utils::amf::as_number;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishType {
+    /// Publish without recording on the server.
+    Live,
+    /// Record to a new file, replacing an existing one.
+    Record,
+    /// Record and append to an existing file.
+    Append,
+}
+
+impl PublishType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PublishType::Live => "live",
+            PublishType::Record => "record",
+            PublishType::Append => "append",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum NetStreamEvent {
+    /// The server answered createStream with this message stream id.
+    Created { stream_id: u32 },
+    Status(NetStatus),
+    Data(DataMessage),
+    Audio { timestamp: u32, audio: AudioMessage },
+    Video { timestamp: u32, video: VideoMessage },
+    Begin,
+    EndOfFile,
+    Dry,
+    Recorded,
+}
+
+impl NetStreamEvent {
+    pub fn is_media(&self) -> bool {
+        matches!(self, NetStreamEvent::Data(_) | NetStreamEvent::Audio { .. } | NetStreamEvent::Video { .. })
+    }
+}
+
+/// The last play or publish of a stream, replayed after a reconnect.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamRequest {
+    Play { name: String, start: f64, duration: f64, reset: bool },
+    Publish { name: String, publish_type: PublishType },
+}
+
+/// A message stream of a `NetConnection`, used to play or publish media.
+#[derive(Clone, Debug, Default)]
+pub struct NetStream {
+    /// Message stream id given by the server, `None` until createStream
+    /// has been answered.
+    pub stream_id: Option<u32>,
+    pub last_request: Option<StreamRequest>,
+
+    pub events: Vec<NetStreamEvent>,
+}
+
+impl NetStream {
+    pub fn new() -> Self {
+        NetStream::default()
+    }
+
+    // shared like shared objects, the Rc inside AMF values keeps both on one thread
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new_net_stream() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Keeps an event for `take_events`. Past `MAX_QUEUED_EVENTS` the oldest
+    /// media event makes room, then the oldest event of any kind.
+    pub(crate) fn dispatch_event(&mut self, event: NetStreamEvent) {
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            let index = self.events.iter().position(NetStreamEvent::is_media).unwrap_or(0);
+            self.events.remove(index);
+        }
+
+        self.events.push(event);
+    }
+
+    /// Returns the events received since the last call, the last
+    /// `MAX_QUEUED_EVENTS` at most.
+    pub fn take_events(&mut self) -> Vec<NetStreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn get_stream_id(net_stream: &Arc<Mutex<NetStream>>) -> std::io::Result<u32> {
+        net_stream.lock().unwrap().stream_id.ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotConnected,
+            "NetStream hasn't been created yet",
+        ))
+    }
+
+    fn send_stream_command<T: Transport>(
+        net_stream: &Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        procedure_name: &str,
+        optional_arguments: Vec<Value>,
+    ) -> std::io::Result<()> {
+        let stream_id = NetStream::get_stream_id(net_stream)?;
+
+        connection
+            .call_on_stream(stream_id, procedure_name, None, optional_arguments, None)
+            .map(|_| ())
+    }
+
+    /// Asks the server for a message stream through createStream. The stream
+    /// can be used once its `Created` event came in, a failure comes in as an
+    /// error `Status` event.
+    pub fn create<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<()> {
+        let created_stream = net_stream.clone();
+        let failed_stream = net_stream.clone();
+
+        let transaction_id = connection
+            .get_context()
+            .transaction_manager
+            .initialize_transaction(Responder::with_error(
+                move |_, information: &[Value]| {
+                    let mut net_stream = created_stream.lock().unwrap();
+
+                    match information.first().and_then(as_number) {
+                        Some(stream_id) => {
+                            let stream_id = stream_id as u32;
+                            net_stream.stream_id = Some(stream_id);
+                            net_stream.dispatch_event(NetStreamEvent::Created { stream_id });
+                        }
+                        None => net_stream.dispatch_event(NetStreamEvent::Status(NetStatus::error(
+                            NetStatusCode::CallFailed,
+                            "createStream answered without a stream id",
+                        ))),
+                    }
+                },
+                move |_, information: &[Value]| {
+                    // errors, timeouts and dropped connections all carry a status
+                    let status = NetStatus::from_arguments(information)
+                        .unwrap_or_else(|| NetStatus::error(NetStatusCode::CallFailed, "createStream failed"));
+
+                    failed_stream.lock().unwrap().dispatch_event(NetStreamEvent::Status(status));
+                },
+            ))?;
+
+        connection.get_context().add_net_stream(net_stream);
+
+        connection.send_command(
+            AMFCommandMessage {
+                procedure_name: "createStream".to_string(),
+                transaction_id,
+                command_object: None,
+                optional_arguments: vec![],
+            },
+            RTMPMessageHeader::default(),
+        )
+    }
+
+    /// Plays `name` starting at `start` seconds for `duration` seconds. A start
+    /// of -2 plays live and falls back to recorded, -1 only plays live. A
+    /// duration of -1 plays until the end.
+    pub fn play<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        name: &str,
+        start: f64,
+        duration: f64,
+        reset: bool,
+    ) -> std::io::Result<()> {
+        net_stream.lock().unwrap().last_request = Some(StreamRequest::Play {
+            name: name.to_string(),
+            start,
+            duration,
+            reset,
+        });
+
+        NetStream::send_stream_command(
+            &net_stream,
+            connection,
+            "play",
+            vec![
+                Value::String(name.to_string()),
+                Value::Number(start),
+                Value::Number(duration),
+                Value::Bool(reset),
+            ],
+        )
+    }
+
+    pub fn publish<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        name: &str,
+        publish_type: PublishType,
+    ) -> std::io::Result<()> {
+        net_stream.lock().unwrap().last_request = Some(StreamRequest::Publish {
+            name: name.to_string(),
+            publish_type,
+        });
+
+        NetStream::send_stream_command(
+            &net_stream,
+            connection,
+            "publish",
+            vec![
+                Value::String(name.to_string()),
+                Value::String(publish_type.as_str().to_string()),
+            ],
+        )
+    }
+
+    /// Pauses or resumes playback, `milliseconds` being the current position.
+    pub fn pause<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        pause: bool,
+        milliseconds: f64,
+    ) -> std::io::Result<()> {
+        NetStream::send_stream_command(
+            &net_stream,
+            connection,
+            "pause",
+            vec![Value::Bool(pause), Value::Number(milliseconds)],
+        )
+    }
+
+    pub fn seek<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        milliseconds: f64,
+    ) -> std::io::Result<()> {
+        NetStream::send_stream_command(
+            &net_stream,
+            connection,
+            "seek",
+            vec![Value::Number(milliseconds)],
+        )
+    }
+
+    pub fn receive_audio<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        receive: bool,
+    ) -> std::io::Result<()> {
+        NetStream::send_stream_command(&net_stream, connection, "receiveAudio", vec![Value::Bool(receive)])
+    }
+
+    pub fn receive_video<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        receive: bool,
+    ) -> std::io::Result<()> {
+        NetStream::send_stream_command(&net_stream, connection, "receiveVideo", vec![Value::Bool(receive)])
+    }
+
+    /// Tells the server how many milliseconds of media the client buffers.
+    pub fn set_buffer_length<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        buffer_length: u32,
+    ) -> std::io::Result<()> {
+        let stream_id = NetStream::get_stream_id(&net_stream)?;
+
+        connection.send_set_buffer_length(stream_id, buffer_length)
+    }
+
+    fn send_media<T: Transport>(
+        net_stream: &Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        timestamp: u32,
+        message: RTMPMessageType,
+    ) -> std::io::Result<()> {
+        let stream_id = NetStream::get_stream_id(net_stream)?;
+
+        connection.send_message(
+            message,
+            RTMPMessageHeader {
+                timestamp,
+                message_stream_id: stream_id,
+            },
+        )
+    }
+
+    /// Sends an audio message on a stream that is publishing.
+    pub fn send_audio<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        timestamp: u32,
+        audio: AudioMessage,
+    ) -> std::io::Result<()> {
+        NetStream::send_media(&net_stream, connection, timestamp, RTMPMessageType::Audio(audio))
+    }
+
+    /// Sends a video message on a stream that is publishing.
+    pub fn send_video<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        timestamp: u32,
+        video: VideoMessage,
+    ) -> std::io::Result<()> {
+        NetStream::send_media(&net_stream, connection, timestamp, RTMPMessageType::Video(video))
+    }
+
+    /// Sends a data message, such as @setDataFrame, on a stream that is publishing.
+    pub fn send_data<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+        timestamp: u32,
+        data: DataMessage,
+    ) -> std::io::Result<()> {
+        NetStream::send_media(&net_stream, connection, timestamp, RTMPMessageType::Data(data))
+    }
+
+    /// Stops playing or publishing, the stream can be reused afterwards.
+    pub fn close<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<()> {
+        net_stream.lock().unwrap().last_request = None;
+
+        NetStream::send_stream_command(&net_stream, connection, "closeStream", vec![])
+    }
+
+    /// Sends the last play or publish again, on a stream created anew.
+    pub(crate) fn replay<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<()> {
+        let last_request = net_stream.lock().unwrap().last_request.clone();
+
+        match last_request {
+            Some(StreamRequest::Play { name, start, duration, reset }) => {
+                NetStream::play(net_stream, connection, &name, start, duration, reset)
+            }
+            Some(StreamRequest::Publish { name, publish_type }) => {
+                NetStream::publish(net_stream, connection, &name, publish_type)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Releases the message stream on the server, sent over stream 0.
+    pub fn delete<T: Transport>(
+        net_stream: Arc<Mutex<NetStream>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<()> {
+        let stream_id = NetStream::get_stream_id(&net_stream)?;
+
+        connection.send_command(
+            AMFCommandMessage {
+                procedure_name: "deleteStream".to_string(),
+                transaction_id: 0,
+                command_object: None,
+                optional_arguments: vec![Value::Number(stream_id as f64)],
+            },
+            RTMPMessageHeader::default(),
+        )?;
+
+        let context = connection.get_context();
+        context.remove_net_stream(stream_id);
+        context.outgoing.allocator.release(stream_id);
+
+        let mut net_stream = net_stream.lock().unwrap();
+        net_stream.stream_id = None;
+        net_stream.last_request = None;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::allocate_net_connection_context;
+    use crate::media::packets::{FrameType, VideoCodecId, VideoTagHeader};
+    use crate::net_connection::reader::RTMPReader;
+    use crate::net_connection::state::ConnectionState;
+    use crate::net_connection::writer::RTMPWriter;
+    use crate::transport::memory_transport::MemoryTransport;
+
+    /// Runs `send` on a stream with id 1 and returns the command the server got.
+    fn sent_command<F>(send: F) -> (u32, AMFCommandMessage)
+    where
+        F: FnOnce(Arc<Mutex<NetStream>>, &mut NetConnection<MemoryTransport>) -> std::io::Result<()>,
+    {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        let mut server = allocate_net_connection_context(server);
+
+        let net_stream = NetStream::new_net_stream();
+        net_stream.lock().unwrap().stream_id = Some(1);
+        send(net_stream, &mut connection).unwrap();
+
+        match RTMPReader::read(&mut server).unwrap() {
+            (header, RTMPMessageType::AMF0Command(command)) => (header.message_stream_id, command),
+            (_, message) => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_play() {
+        let (stream_id, command) =
+            sent_command(|net_stream, connection| NetStream::play(net_stream, connection, "live", -2.0, -1.0, true));
+
+        assert_eq!(stream_id, 1);
+        assert_eq!(command.procedure_name, "play");
+        assert_eq!(command.transaction_id, 0);
+        assert_eq!(
+            command.optional_arguments,
+            vec![
+                Value::String("live".to_string()),
+                Value::Number(-2.0),
+                Value::Number(-1.0),
+                Value::Bool(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_publish() {
+        let (stream_id, command) = sent_command(|net_stream, connection| {
+            NetStream::publish(net_stream, connection, "live", PublishType::Record)
+        });
+
+        assert_eq!(stream_id, 1);
+        assert_eq!(command.procedure_name, "publish");
+        assert_eq!(
+            command.optional_arguments,
+            vec![Value::String("live".to_string()), Value::String("record".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_pause_seek_close() {
+        let (_, command) = sent_command(|net_stream, connection| NetStream::pause(net_stream, connection, true, 1500.0));
+        assert_eq!(command.procedure_name, "pause");
+        assert_eq!(command.optional_arguments, vec![Value::Bool(true), Value::Number(1500.0)]);
+
+        let (_, command) = sent_command(|net_stream, connection| NetStream::seek(net_stream, connection, 3000.0));
+        assert_eq!(command.procedure_name, "seek");
+        assert_eq!(command.optional_arguments, vec![Value::Number(3000.0)]);
+
+        let (stream_id, command) = sent_command(NetStream::close);
+        assert_eq!(stream_id, 1);
+        assert_eq!(command.procedure_name, "closeStream");
+    }
+
+    #[test]
+    fn test_delete() {
+        // deleteStream goes over stream 0 with the id as argument
+        let (stream_id, command) = sent_command(NetStream::delete);
+
+        assert_eq!(stream_id, 0);
+        assert_eq!(command.procedure_name, "deleteStream");
+        assert_eq!(command.optional_arguments, vec![Value::Number(1.0)]);
+    }
+
+    #[test]
+    fn test_create_with_integer_id() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        let mut server = allocate_net_connection_context(server);
+
+        let net_stream = NetStream::new_net_stream();
+        NetStream::create(net_stream.clone(), &mut connection).unwrap();
+
+        let transaction_id = match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::AMF0Command(command)) => command.transaction_id,
+            (_, message) => panic!("unexpected message {:?}", message),
+        };
+
+        // AMF3 servers answer with an integer
+        let answer = AMFCommandMessage {
+            procedure_name: "_result".to_string(),
+            transaction_id,
+            command_object: None,
+            optional_arguments: vec![Value::Integer(5)],
+        };
+        RTMPWriter::write(RTMPMessageType::AMF3Command(answer), &mut server).unwrap();
+        connection.process_messages().unwrap();
+
+        assert_eq!(net_stream.lock().unwrap().stream_id, Some(5));
+    }
+
+    #[test]
+    fn test_create_failure() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        let mut server = allocate_net_connection_context(server);
+
+        let net_stream = NetStream::new_net_stream();
+        NetStream::create(net_stream.clone(), &mut connection).unwrap();
+
+        let transaction_id = match RTMPReader::read(&mut server).unwrap() {
+            (_, RTMPMessageType::AMF0Command(command)) => command.transaction_id,
+            (_, message) => panic!("unexpected message {:?}", message),
+        };
+
+        let answer = AMFCommandMessage {
+            procedure_name: "_error".to_string(),
+            transaction_id,
+            command_object: None,
+            optional_arguments: vec![NetStatus::error(NetStatusCode::CallFailed, "no streams left").to_value()],
+        };
+        RTMPWriter::write(RTMPMessageType::AMF0Command(answer), &mut server).unwrap();
+        connection.process_messages().unwrap();
+
+        let mut net_stream = net_stream.lock().unwrap();
+        assert_eq!(net_stream.stream_id, None);
+        match net_stream.take_events().as_slice() {
+            [NetStreamEvent::Status(status)] => {
+                assert!(status.is_error());
+                assert_eq!(status.description.as_deref(), Some("no streams left"));
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_undrained_stream_events() {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = NetConnection::new(client);
+        connection.context.state = ConnectionState::Connected;
+        let mut server = allocate_net_connection_context(server);
+
+        let net_stream = NetStream::new_net_stream();
+        net_stream.lock().unwrap().stream_id = Some(1);
+        connection.context.add_net_stream(net_stream.clone());
+
+        let video = VideoMessage {
+            header: VideoTagHeader {
+                frame_type: FrameType::InterFrame,
+                codec_id: VideoCodecId::SorensonH263,
+                avc_packet_type: None,
+                composition_time: None,
+            },
+            data: vec![0; 16],
+        };
+
+        let count = MAX_QUEUED_EVENTS as u32 + 10;
+        for timestamp in 0..count {
+            let header = RTMPMessageHeader { timestamp, message_stream_id: 1 };
+            RTMPWriter::write_with_header(RTMPMessageType::Video(video.clone()), header, &mut server).unwrap();
+        }
+        for _ in 0..count {
+            connection.process_messages().unwrap();
+        }
+
+        // nobody drained either queue, both only hold the latest frames
+        assert_eq!(connection.context.events.len(), MAX_QUEUED_EVENTS);
+        let events = net_stream.lock().unwrap().take_events();
+        assert_eq!(events.len(), MAX_QUEUED_EVENTS);
+        assert!(matches!(events.first(), Some(NetStreamEvent::Video { timestamp: 10, .. })));
+    }
+}
@@ -105,6 +105,28 @@ impl SharedObject {
         Ok(())
     }
 
+    /// Stops using the shared object, the server won't send changes anymore.
+    pub fn close<T: Transport>(
+        shared_object: Arc<Mutex<SharedObject>>,
+        connection: &mut NetConnection<T>,
+    ) -> std::io::Result<()> {
+        let name;
+
+        {
+            let mut shared_object = shared_object.lock().unwrap();
+            shared_object.clear_events();
+            shared_object.dispatch_event(SharedObjectEvent::Release);
+            shared_object.use_success = false;
+
+            name = shared_object.name.clone();
+        }
+
+        let result = connection.send_shared_object(name.clone());
+        connection.get_context().remove_shared_object(&name);
+
+        result
+    }
+
     pub fn get_property(&self, key: &str) -> Option<Value> {
         let data = self.data.lock().unwrap();
         data.get(key).cloned()
@@ -153,34 +175,20 @@ impl SharedObject {
         Ok(())
     }
 
+    /// Applies the events received from the server to the data, they reach
+    /// the application through NetConnectionEvent::SharedObjectSync.
     pub fn process_events(&mut self) {
-        println!("Processing events");
         for event in self.events.iter() {
             match event {
-                SharedObjectEvent::Use => {
-                    println!("Use event");
-                }
-                SharedObjectEvent::Release => {
-                    println!("Release event");
-                }
                 SharedObjectEvent::Change { key, value } => {
                     let mut data = self.data.lock().unwrap();
                     data.insert(key.clone(), value.clone());
                 }
-                SharedObjectEvent::Success => {
-                    println!("Success event");
-                }
-                SharedObjectEvent::SendMessage => {
-                    println!("SendMessage event");
-                }
-                SharedObjectEvent::Status { code, level } => {
-                    println!("Status event: {} {}", code, level);
-                }
                 SharedObjectEvent::Clear => {
-                    println!("Clear event");
+                    self.data.lock().unwrap().clear();
                 }
                 SharedObjectEvent::Remove { key } => {
-                    println!("Remove event: {}", key);
+                    self.data.lock().unwrap().remove(key);
                 }
                 SharedObjectEvent::UseSuccess => {
                     self.use_success = true;
@@ -44,7 +44,9 @@ impl<'a> SharedObjectReader<'a> {
 
             while !i.is_empty() {
                 let (j, event) = self.read_event(i)?;
-                shared_object.dispatch_event(event);
+                if let Some(event) = event {
+                    shared_object.dispatch_event(event);
+                }
                 i = j;
             }
 
@@ -64,42 +66,32 @@ impl<'a> SharedObjectReader<'a> {
     fn read_change_event<'b>(&self, payload: &'b [u8]) -> RTMPResult<'b, SharedObjectEvent> {
         // TODO: handle case where the event payload size is 0
         let (i, key) = self.read_string(payload)?;
-        let (i, value) = AMF0Decoder::default().parse_single_element(i).unwrap();
-
-        if !i.is_empty() {
-            println!(
-                "for some reason, there are still bytes left in the payload: {:?}",
-                i
-            );
-        }
+        let (i, value) = AMF0Decoder::default()
+            .parse_single_element(i)
+            .map_err(|_| nom::Err::Error(Error::Nom(i, nom::error::ErrorKind::Verify)))?;
 
         Ok((i, SharedObjectEvent::Change {
             key,
-            value: Rc::try_unwrap(value).unwrap(),
+            value: Rc::try_unwrap(value).unwrap_or_else(|value| (*value).clone()),
         }))
     }
 
-    fn read_event<'b>(&self, payload: &'b [u8]) -> RTMPResult<'b, SharedObjectEvent> {
+    /// Reads one event, `None` for the types we don't handle.
+    fn read_event<'b>(&self, payload: &'b [u8]) -> RTMPResult<'b, Option<SharedObjectEvent>> {
         let (i, event_type) = be_u8(payload)?;
         let (i, event_length) = be_u32(i)?;
         let (i, event_payload) = take(event_length as usize)(i)?;
 
-        let (i, event) = match event_type {
+        // the event payload is read on its own, reading continues after it
+        let event = match event_type {
             // 0x01 => self.read_use_event(event_payload)?,
             // 0x02 => self.read_release_event(event_payload)?,
-            0x04 => self.read_change_event(event_payload)?,
+            0x04 => Some(self.read_change_event(event_payload)?.1),
             // 0x06 => self.send_message_event(event_payload)?,
-            // 0x08 => self.read_clear_event(event_payload)?,
-            // 0x09 => self.read_remove_event(event_payload)?,
-            0x0b => (i, SharedObjectEvent::UseSuccess),
-            // _ => Err(std::io::Error::new(
-            //     std::io::ErrorKind::InvalidData,
-            //     format!("Unknown event type: {:?}", event_type),
-            // ))?
-            _ => {
-                println!("Unknown event type: {:?}", event_type);
-                (i, SharedObjectEvent::UseSuccess)
-            }
+            0x08 => Some(SharedObjectEvent::Clear),
+            0x09 => Some(SharedObjectEvent::Remove { key: self.read_string(event_payload)?.1 }),
+            0x0b => Some(SharedObjectEvent::UseSuccess),
+            _ => None,
         };
 
         Ok((i, event))
@@ -48,7 +48,7 @@ impl SharedObjectWriter {
         let event_type = event.get_type();
 
         match event {
-            SharedObjectEvent::Use => {}
+            SharedObjectEvent::Use | SharedObjectEvent::Release => {}
             SharedObjectEvent::RequestChange { key, value } => {
                 self.write_request_change_event(key, value, &mut event_payload)?;
             }
@@ -79,7 +79,6 @@ impl SharedObjectWriter {
         payload_vector.extend_from_slice(&0u32.to_be_bytes());
 
         for event in shared_object.events.iter() {
-            println!("event: {:?}", event);
             self.write_shared_object_event(event.clone(), payload_vector)?;
         }
 
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::transport::Transport;
+
+/// Wait for the peer when no timeout is set, so a broken test fails instead of hanging.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transport over channels, for tests. Connecting hands the other end of a
+/// new link to whoever holds the listener, like accepting a TCP connection.
+#[derive(Debug)]
+pub(crate) struct MemoryTransport {
+    connector: Option<Sender<MemoryTransport>>,
+    sender: Option<Sender<Vec<u8>>>,
+    receiver: Option<Receiver<Vec<u8>>>,
+    buffer: VecDeque<u8>,
+    timeout: Option<Duration>,
+}
+
+impl MemoryTransport {
+    fn new() -> Self {
+        MemoryTransport {
+            connector: None,
+            sender: None,
+            receiver: None,
+            buffer: VecDeque::new(),
+            timeout: None,
+        }
+    }
+
+    /// Both ends of a link, what one writes the other reads.
+    pub fn pair() -> (Self, Self) {
+        let (a_sender, b_receiver) = channel();
+        let (b_sender, a_receiver) = channel();
+
+        let mut a = MemoryTransport::new();
+        a.sender = Some(a_sender);
+        a.receiver = Some(a_receiver);
+
+        let mut b = MemoryTransport::new();
+        b.sender = Some(b_sender);
+        b.receiver = Some(b_receiver);
+
+        (a, b)
+    }
+
+    /// An unconnected transport, every connect sends the server end of a new
+    /// link to the returned receiver.
+    pub fn listen() -> (Self, Receiver<MemoryTransport>) {
+        let (connector, listener) = channel();
+
+        let mut transport = MemoryTransport::new();
+        transport.connector = Some(connector);
+
+        (transport, listener)
+    }
+
+    /// A transport reading the given bytes, then the end of the stream.
+    pub fn with_data(data: &[u8]) -> Self {
+        let mut transport = MemoryTransport::new();
+        let (sender, receiver) = channel();
+        transport.receiver = Some(receiver);
+        transport.buffer.extend(data);
+        drop(sender);
+
+        transport
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn connect(&mut self, _ip: String, _port: u16) -> io::Result<()> {
+        let connector = self
+            .connector
+            .as_ref()
+            .ok_or(io::Error::new(ErrorKind::ConnectionRefused, "Nothing is listening"))?;
+
+        let (client, server) = MemoryTransport::pair();
+        connector
+            .send(server)
+            .map_err(|_| io::Error::new(ErrorKind::ConnectionRefused, "Listener is gone"))?;
+
+        self.sender = client.sender;
+        self.receiver = client.receiver;
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> io::Result<()> {
+        self.buffer.clear();
+
+        match (self.sender.take(), self.receiver.take()) {
+            (None, None) => Err(io::Error::new(ErrorKind::BrokenPipe, "Not connected")),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn wait_for_data(&mut self, timeout: Duration) -> io::Result<bool> {
+        if !self.buffer.is_empty() {
+            return Ok(true);
+        }
+
+        let receiver = self
+            .receiver
+            .as_ref()
+            .ok_or(io::Error::new(ErrorKind::BrokenPipe, "Not connected"))?;
+
+        match receiver.recv_timeout(timeout) {
+            Ok(data) => {
+                self.buffer.extend(data);
+                Ok(true)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(false),
+            Err(RecvTimeoutError::Disconnected) => Ok(true),
+        }
+    }
+
+    fn read_data(&mut self, size: usize) -> io::Result<Vec<u8>> {
+        while self.buffer.len() < size {
+            let receiver = self
+                .receiver
+                .as_ref()
+                .ok_or(io::Error::new(ErrorKind::BrokenPipe, "Not connected"))?;
+
+            match receiver.recv_timeout(self.timeout.unwrap_or(DEFAULT_TIMEOUT)) {
+                Ok(data) => self.buffer.extend(data),
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(ErrorKind::TimedOut, "Read timed out")),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(ErrorKind::UnexpectedEof, "Peer closed the link"))
+                }
+            }
+        }
+
+        Ok(self.buffer.drain(..size).collect())
+    }
+
+    fn write_data(&mut self, data: Vec<u8>) -> io::Result<()> {
+        self.sender
+            .as_ref()
+            .ok_or(io::Error::new(ErrorKind::BrokenPipe, "Not connected"))?
+            .send(data)
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "Peer closed the link"))
+    }
+}
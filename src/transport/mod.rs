@@ -1,12 +1,32 @@
 
 pub mod tcp_transport;
+#[cfg(test)]
+pub(crate) mod memory_transport;
 
 use std::io::Result;
+use std::time::Duration;
 
 pub trait Transport: Send {
     fn connect(&mut self, ip: String, port: u16) -> Result<()>;
     fn disconnect(&mut self) -> Result<()>;
 
+    /// Bounds connecting, reading and writing, `None` waits forever.
+    fn set_timeout(&mut self, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    /// The timeout last set, `None` when waiting forever or unsupported.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Waits at most `timeout` for data to read, without consuming it.
+    /// Returns false if none came in. Transports that can't wait claim data
+    /// is there, reads then block as usual.
+    fn wait_for_data(&mut self, _timeout: Duration) -> Result<bool> {
+        Ok(true)
+    }
+
     fn read_data(&mut self, size: usize) -> Result<Vec<u8>>;
     fn write_data(&mut self, data: Vec<u8>) -> Result<()>;
 
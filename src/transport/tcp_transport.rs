@@ -1,13 +1,17 @@
 use crate::transport::Transport;
 use std::{
     io::{self, ErrorKind, Write, Read}, net::{
-        IpAddr, Shutdown, SocketAddr, TcpStream
+        Shutdown, TcpStream, ToSocketAddrs
     }, time::Duration
 };
 
+/// Time given to the server to accept the connection when no timeout is set.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug)]
 pub struct TcpTransport {
     stream: Option<TcpStream>,
+    timeout: Option<Duration>,
 
     written_bytes: u32,
     received_bytes: u32,
@@ -17,6 +21,7 @@ impl TcpTransport {
     pub fn new() -> Self {
         TcpTransport {
             stream: None,
+            timeout: None,
 
             written_bytes: 0,
             received_bytes: 0
@@ -26,21 +31,56 @@ impl TcpTransport {
 
 impl Transport for TcpTransport {
     fn connect(&mut self, ip: String, port: u16) -> std::io::Result<()> {
-        let ip_addr: IpAddr = ip.parse().map_err(|_| {
-            io::Error::new(ErrorKind::InvalidInput, "Failed to parse IP address")
-        })?;
+        // Host names resolve to several addresses, the first one accepting wins
+        let socket_addrs = (ip.as_str(), port).to_socket_addrs()?;
+
+        let mut last_error = io::Error::new(ErrorKind::InvalidInput, "Failed to resolve host");
+        for socket_addr in socket_addrs {
+            match TcpStream::connect_timeout(&socket_addr, self.timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)) {
+                Ok(stream) => {
+                    stream.set_read_timeout(self.timeout)?;
+                    stream.set_write_timeout(self.timeout)?;
+
+                    self.stream = Some(stream);
+                    return Ok(());
+                },
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
 
-        // Create a socket addr object
-        let socket_addr = SocketAddr::new(ip_addr, port);
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.timeout = timeout;
 
-        // Attempt to connect with a timeout of 10s
-        // TODO: maybe make this configurable?
+        if let Some(ref stream) = self.stream {
+            stream.set_read_timeout(timeout)?;
+            stream.set_write_timeout(timeout)?;
+        }
+
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
 
-        match TcpStream::connect_timeout(&socket_addr, Duration::from_secs(10)) {
-            Ok(stream) => {
-                self.stream = Some(stream);
-                Ok(())
-            },
+    fn wait_for_data(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        let stream = match self.stream {
+            Some(ref stream) => stream,
+            None => return Err(io::Error::new(ErrorKind::BrokenPipe, "Stream hasn't been opened yet or was closed.")),
+        };
+
+        // a zero timeout would mean blocking forever
+        stream.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let result = stream.peek(&mut [0u8; 1]);
+        stream.set_read_timeout(self.timeout)?;
+
+        match result {
+            // end of stream counts as ready, the read reports it
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
             Err(e) => Err(e),
         }
     }
@@ -0,0 +1,51 @@
+use flash_lso::types::{Element, Value};
+use std::rc::Rc;
+
+/// Strips an AMF0 `avmplus-object` switch, returning the AMF3 value it wraps.
+pub fn unwrap_amf3(value: Value) -> Value {
+    match value {
+        Value::AMF3(inner) => Rc::try_unwrap(inner).unwrap_or_else(|inner| (*inner).clone()),
+        _ => value,
+    }
+}
+
+/// Properties of an object or ECMA array, empty for any other value.
+pub fn get_properties(value: &Value) -> &[Element] {
+    match value {
+        Value::Object(elements, _) => elements,
+        Value::ECMAArray(_, elements, _) => elements,
+        Value::AMF3(inner) => get_properties(inner),
+        _ => &[],
+    }
+}
+
+pub fn get_property<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    get_properties(value)
+        .iter()
+        .find(|element| element.name == name)
+        .map(|element| match element.value.as_ref() {
+            Value::AMF3(inner) => inner.as_ref(),
+            value => value,
+        })
+}
+
+pub fn get_string<'a>(value: &'a Value, name: &str) -> Option<&'a str> {
+    match get_property(value, name)? {
+        Value::String(s) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+pub fn get_number(value: &Value, name: &str) -> Option<f64> {
+    as_number(get_property(value, name)?)
+}
+
+/// A number, whether sent as an AMF0 number or an AMF3 integer.
+pub fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => Some(*n),
+        Value::Integer(n) => Some(*n as f64),
+        Value::AMF3(inner) => as_number(inner),
+        _ => None,
+    }
+}
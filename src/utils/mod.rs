@@ -1,2 +1,4 @@
+pub mod amf;
 pub mod nom;
-pub mod url;
\ No newline at end of file
+pub mod tea;
+pub mod url;
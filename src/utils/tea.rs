@@ -0,0 +1,134 @@
+//! XXTEA as used by Wowza's SecureToken, ported from rtmpdump's `DecodeTEA`.
+
+const DELTA: u32 = 0x9e3779b9;
+
+/// Packs the first 16 bytes of the secret into four little endian words.
+fn key_words(key: &str) -> [u32; 4] {
+    let mut words = [0u32; 4];
+
+    for (i, byte) in key.as_bytes().iter().take(16).enumerate() {
+        words[i / 4] |= (*byte as u32) << ((i % 4) * 8);
+    }
+
+    words
+}
+
+fn mx(sum: u32, y: u32, z: u32, p: usize, e: u32, key: &[u32; 4]) -> u32 {
+    (((z >> 5) ^ (y << 2)).wrapping_add((y >> 3) ^ (z << 4)))
+        ^ ((sum ^ y).wrapping_add(key[(p & 3) ^ e as usize] ^ z))
+}
+
+fn hex_value(digit: u8) -> u32 {
+    (digit as char).to_digit(16).unwrap_or(0)
+}
+
+/// Decrypts a hex encoded challenge with the shared secret.
+pub fn decrypt_token(key: &str, token: &str) -> Vec<u8> {
+    let key = key_words(key);
+    let token = token.as_bytes();
+
+    let n = token.len().div_ceil(8);
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // little endian words out of hex digits, missing digits count as zero
+    let mut v: Vec<u32> = (0..n)
+        .map(|i| {
+            (0..4).fold(0u32, |word, byte| {
+                let high = token.get(i * 8 + byte * 2).copied().map_or(0, hex_value);
+                let low = token.get(i * 8 + byte * 2 + 1).copied().map_or(0, hex_value);
+                word | ((high << 4) + low) << (byte * 8)
+            })
+        })
+        .collect();
+
+    let rounds = 6 + 52 / n as u32;
+    let mut sum = rounds.wrapping_mul(DELTA);
+    let mut y = v[0];
+
+    while sum != 0 {
+        let e = (sum >> 2) & 3;
+
+        for p in (1..n).rev() {
+            let z = v[p - 1];
+            v[p] = v[p].wrapping_sub(mx(sum, y, z, p, e, &key));
+            y = v[p];
+        }
+
+        let z = v[n - 1];
+        v[0] = v[0].wrapping_sub(mx(sum, y, z, 0, e, &key));
+        y = v[0];
+
+        sum = sum.wrapping_sub(DELTA);
+    }
+
+    let mut plain: Vec<u8> = v.iter().flat_map(|word| word.to_le_bytes()).collect();
+    plain.truncate(token.len() / 2);
+    plain
+}
+
+/// Encrypts to the hex form servers send, the counterpart of `decrypt_token`.
+pub fn encrypt_token(key: &str, plain: &[u8]) -> String {
+    let key = key_words(key);
+
+    let mut padded = plain.to_vec();
+    padded.resize(plain.len().div_ceil(4).max(1) * 4, 0);
+
+    let mut v: Vec<u32> = padded
+        .chunks(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    let n = v.len();
+
+    let mut rounds = 6 + 52 / n as u32;
+    let mut sum = 0u32;
+    let mut z = v[n - 1];
+
+    while rounds > 0 {
+        sum = sum.wrapping_add(DELTA);
+        let e = (sum >> 2) & 3;
+
+        for p in 0..n - 1 {
+            let y = v[p + 1];
+            v[p] = v[p].wrapping_add(mx(sum, y, z, p, e, &key));
+            z = v[p];
+        }
+
+        let y = v[0];
+        v[n - 1] = v[n - 1].wrapping_add(mx(sum, y, z, n - 1, e, &key));
+        z = v[n - 1];
+
+        rounds -= 1;
+    }
+
+    v.iter()
+        .flat_map(|word| word.to_le_bytes())
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_answers() {
+        // reference XXTEA results, DecodeTEA is plain XXTEA decryption of
+        // little endian words: an all zero key and block
+        assert_eq!(decrypt_token("", "ab043705808c5d57"), vec![0; 8]);
+
+        // key words 01020304 05060708 090a0b0c 0d0e0f10, block 01020304 05060708
+        let key = "\x04\x03\x02\x01\x08\x07\x06\x05\x0c\x0b\x0a\x09\x10\x0f\x0e\x0d";
+        assert_eq!(decrypt_token(key, "be97648dc3d139a6"), vec![4, 3, 2, 1, 8, 7, 6, 5]);
+        assert_eq!(encrypt_token(key, &[4, 3, 2, 1, 8, 7, 6, 5]), "be97648dc3d139a6");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let encrypted = encrypt_token("mySharedSecret", b"a1b2c3d4e5f6g7h8");
+        assert_eq!(encrypted.len(), 32);
+        assert_eq!(decrypt_token("mySharedSecret", &encrypted), b"a1b2c3d4e5f6g7h8");
+        assert_ne!(decrypt_token("wrongSecret", &encrypted), b"a1b2c3d4e5f6g7h8");
+    }
+}